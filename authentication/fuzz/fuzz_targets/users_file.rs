@@ -0,0 +1,20 @@
+// users.json is edited by hand, so the parser sees anything. It must reject
+// what it can't read without panicking, in any layout version, and whatever
+// it accepts must be written back and read again unchanged.
+#![no_main]
+
+use authentication::{decode_users, encode_users, encryption, USERS_FILE_VERSION};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(contents) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = encryption::kdf_of(contents);
+    if let Ok((users, _)) = decode_users(contents) {
+        let saved = encode_users(&users).expect("parsed users encode");
+        let (again, version) = decode_users(&saved).expect("saved users parse");
+        assert_eq!(version, USERS_FILE_VERSION);
+        assert_eq!(again, users);
+    }
+});
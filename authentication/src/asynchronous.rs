@@ -0,0 +1,302 @@
+// The same operations for code running on a Tokio runtime. File I/O and
+// password hashing block, so they run on the blocking thread pool instead of
+// stalling the async worker threads.
+use crate::{
+    check_login, effective_action, load_groups, load_users, login_hash, manage, save_users,
+    unix_seconds, update_users_file, Groups, LoginAction, Password, User, Username,
+};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::{Mutex, Semaphore};
+
+pub type UserMap = HashMap<Username, User>;
+
+// Tells one version of a store from the next, see `Authenticator::refresh`.
+// For files, their modification times and sizes, `None` where there is none.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stamp(pub Vec<Option<(SystemTime, u64)>>);
+
+// Somewhere the users, and the groups they are in, are kept.
+pub trait UserStore: Send + Sync {
+    fn load(&self) -> impl Future<Output = io::Result<UserMap>> + Send;
+    fn load_groups(&self) -> impl Future<Output = io::Result<Groups>> + Send;
+    fn stamp(&self) -> impl Future<Output = io::Result<Stamp>> + Send;
+    fn save(&self, users: UserMap) -> impl Future<Output = io::Result<()>> + Send;
+    // Record an accepted login in the store as it is now, not as it was
+    // loaded, so nothing saved there since is undone.
+    fn record_login(
+        &self,
+        username: Username,
+        now: SystemTime,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+// The users and groups files, read with `load_users` and `load_groups`.
+pub struct FileStore {
+    path: PathBuf,
+    groups: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>, groups: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            groups: groups.into(),
+        }
+    }
+}
+
+impl UserStore for FileStore {
+    async fn load(&self) -> io::Result<UserMap> {
+        let path = self.path.clone();
+        blocking(move || load_users(path)).await
+    }
+
+    async fn load_groups(&self) -> io::Result<Groups> {
+        let groups = self.groups.clone();
+        blocking(move || load_groups(groups)).await
+    }
+
+    async fn save(&self, users: UserMap) -> io::Result<()> {
+        let path = self.path.clone();
+        blocking(move || save_users(path, &users)).await
+    }
+
+    async fn stamp(&self) -> io::Result<Stamp> {
+        let (path, groups) = (self.path.clone(), self.groups.clone());
+        let stamp = |path: &Path| match std::fs::metadata(path) {
+            Ok(metadata) => Ok(Some((metadata.modified()?, metadata.len()))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        };
+        blocking(move || Ok(Stamp(vec![stamp(&path)?, stamp(&groups)?]))).await
+    }
+
+    async fn record_login(&self, username: Username, now: SystemTime) -> io::Result<()> {
+        let path = self.path.clone();
+        blocking(move || {
+            // Someone deleted since they logged in has nothing to record.
+            update_users_file(path, |users| manage::record_login(users, &username, now)).map(|_| ())
+        })
+        .await
+    }
+}
+
+// Runs hashes on the blocking pool, with at most `queue` of them handed to it
+// at once. Past that, callers wait their turn (first come, first served), so
+// a burst of logins can't tie up every blocking thread.
+#[derive(Clone)]
+pub struct Hasher {
+    slots: Arc<Semaphore>,
+}
+
+impl Hasher {
+    pub fn new(queue: usize) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(queue)),
+        }
+    }
+
+    pub async fn hash_password(&self, password: Password) -> String {
+        self.run(move || crate::hash_password(&password)).await
+    }
+
+    async fn run<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> T {
+        // The semaphore is never closed.
+        let slot = self.slots.clone().acquire_owned().await.unwrap();
+        blocking(move || {
+            let _slot = slot;
+            job()
+        })
+        .await
+    }
+}
+
+// The users and groups in memory, backed by a store.
+pub struct Authenticator<S> {
+    store: S,
+    users: RwLock<UserMap>,
+    groups: RwLock<Groups>,
+    // Of the store, when `users` and `groups` were loaded.
+    stamp: parking_lot::Mutex<Stamp>,
+    hasher: Hasher,
+    // One save at a time, so an older copy of the users never lands last.
+    saving: Mutex<()>,
+    // One reload at a time, however many logins notice a change at once.
+    reloading: Mutex<()>,
+}
+
+impl<S: UserStore> Authenticator<S> {
+    pub async fn open(store: S, hasher: Hasher) -> io::Result<Self> {
+        // Stamped before loading, so a change made meanwhile is picked up by
+        // the next `refresh` rather than missed.
+        let stamp = parking_lot::Mutex::new(store.stamp().await?);
+        let users = RwLock::new(store.load().await?);
+        let groups = RwLock::new(store.load_groups().await?);
+        Ok(Self {
+            store,
+            users,
+            groups,
+            stamp,
+            hasher,
+            saving: Mutex::new(()),
+            reloading: Mutex::new(()),
+        })
+    }
+
+    // For changes; follow them with `save`.
+    pub fn users(&self) -> &RwLock<UserMap> {
+        &self.users
+    }
+
+    pub fn groups(&self) -> &RwLock<Groups> {
+        &self.groups
+    }
+
+    // Accepted logins are recorded, at most once a second per user. Only the
+    // time is written: see `UserStore::record_login`.
+    pub async fn login(&self, username: &str, password: Password) -> Option<LoginAction> {
+        let username = Username::new(username).ok()?;
+        let hash = self.hasher.run(move || login_hash(&password)).await;
+        let action = check_login(&self.users.read(), &username, &hash)?;
+        let action = effective_action(&self.groups.read(), &username, action);
+        if let LoginAction::Accept(_) = action {
+            let now = SystemTime::now();
+            let recorded = {
+                let mut users = self.users.write();
+                let seen = users.get(&username).and_then(|user| user.last_login);
+                seen != Some(unix_seconds(now))
+                    && manage::record_login(&mut users, &username, now).is_ok()
+            };
+            // A failed save only loses the timestamp: the login stands.
+            if recorded {
+                let _ = self.store.record_login(username, now).await;
+            }
+        }
+        Some(action)
+    }
+
+    pub async fn save(&self) -> io::Result<()> {
+        let _saving = self.saving.lock().await;
+        let users = self.users.read().clone();
+        self.store.save(users).await
+    }
+
+    // Pick up changes made to the store by someone else.
+    pub async fn reload(&self) -> io::Result<()> {
+        let stamp = self.store.stamp().await?;
+        let users = self.store.load().await?;
+        let groups = self.store.load_groups().await?;
+        *self.users.write() = users;
+        *self.groups.write() = groups;
+        *self.stamp.lock() = stamp;
+        Ok(())
+    }
+
+    // `reload`, if the store changed since it was last loaded: e.g. `userman`
+    // or the web admin locked someone. Only costs a look at the store if not.
+    pub async fn refresh(&self) -> io::Result<()> {
+        let stamp = self.store.stamp().await?;
+        if stamp == *self.stamp.lock() {
+            return Ok(());
+        }
+        let _reloading = self.reloading.lock().await;
+        // Someone else may have reloaded while we waited.
+        if stamp != *self.stamp.lock() {
+            self.reload().await?;
+        }
+        Ok(())
+    }
+}
+
+// Panics in `job` are passed on to the caller, as if it had run inline.
+pub async fn blocking<T: Send + 'static>(job: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(job).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manage, Role};
+
+    #[tokio::test]
+    async fn test_authenticator() {
+        let path = std::env::temp_dir().join(format!("async-users-{}.json", std::process::id()));
+        let groups_path =
+            std::env::temp_dir().join(format!("async-groups-{}.json", std::process::id()));
+        let password = Password::from("password");
+        let mut users = UserMap::new();
+        let adam = Username::new("adam").unwrap();
+        manage::add_user(&mut users, &adam, &password, Role::Admin).unwrap();
+        save_users(&path, &users).unwrap();
+
+        let auth = Authenticator::open(FileStore::new(&path, &groups_path), Hasher::new(2))
+            .await
+            .unwrap();
+        let auth = Arc::new(auth);
+        let logins: Vec<_> = (0..8)
+            .map(|_| {
+                let (auth, password) = (auth.clone(), password.clone());
+                tokio::spawn(async move { auth.login(" Adam", password).await })
+            })
+            .collect();
+        for login in logins {
+            assert_eq!(login.await.unwrap(), Some(LoginAction::Accept(Role::Admin)));
+        }
+        assert_eq!(auth.login("adam", Password::from("nope")).await, None);
+        assert!(load_users(&path).unwrap()[&adam].last_login.is_some());
+
+        // Logins don't write back the users as they were loaded: a user
+        // added by someone else since stays.
+        let mantou = Username::new("mantou").unwrap();
+        let mut changed = load_users(&path).unwrap();
+        manage::add_user(&mut changed, &mantou, &password, Role::User).unwrap();
+        changed.get_mut(&adam).unwrap().last_login = None;
+        save_users(&path, &changed).unwrap();
+        // Past the once a second limit.
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        auth.login("adam", password.clone()).await;
+        let saved = load_users(&path).unwrap();
+        assert!(saved.contains_key(&mantou));
+        assert!(saved[&adam].last_login.is_some());
+        assert!(!auth.users().read().contains_key(&mantou));
+        auth.refresh().await.unwrap();
+        assert!(auth.users().read().contains_key(&mantou));
+
+        // Group roles apply, groups file changes included.
+        let mut groups = Groups::new();
+        let ops = Username::new("ops").unwrap();
+        manage::create_group(&mut groups, &ops, Role::Admin).unwrap();
+        manage::add_member(&mut groups, &saved, &ops, &mantou).unwrap();
+        crate::save_groups(&groups_path, &groups).unwrap();
+        auth.refresh().await.unwrap();
+        assert_eq!(
+            auth.login("mantou", password.clone()).await,
+            Some(LoginAction::Accept(Role::Admin))
+        );
+
+        manage::change_password(
+            &mut auth.users().write(),
+            &adam,
+            &Password::from("new password"),
+        )
+        .unwrap();
+        auth.save().await.unwrap();
+        assert_eq!(
+            auth.hasher
+                .hash_password(Password::from("new password"))
+                .await,
+            load_users(&path).unwrap()[&adam].password_hash()
+        );
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(groups_path).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+// Apache htpasswd files, with bcrypt (`htpasswd -B`), SHA-1 (`-s`) and
+// Apache MD5 (`-m`, the default) hashes. Their users have no role of their
+// own, so they all get the one the backend is set up with.
+use super::{AuthBackend, Verdict};
+use crate::{LoginAction, Password, Role, Username};
+use base64::Engine;
+use md5::{Digest, Md5};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+const APR1: &str = "$apr1$";
+const ITOA64: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+pub struct HtpasswdBackend {
+    hashes: HashMap<Username, String>,
+    role: Role,
+}
+
+impl HtpasswdBackend {
+    pub fn load(path: impl AsRef<Path>, role: Role) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?, role)
+    }
+
+    // Refuses the whole file over one bad line, rather than quietly locking
+    // that user out.
+    pub fn parse(text: &str, role: Role) -> io::Result<Self> {
+        let mut hashes = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |problem: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {problem}", number + 1),
+                )
+            };
+            let (username, hash) = line
+                .split_once(':')
+                .ok_or_else(|| invalid("expected `username:hash`"))?;
+            let username = Username::new(username).map_err(|e| invalid(&e.to_string()))?;
+            if !supported(hash) {
+                return Err(invalid("only bcrypt, SHA and apr1 hashes are supported"));
+            }
+            if hashes.insert(username, hash.to_string()).is_some() {
+                return Err(invalid("duplicate user"));
+            }
+        }
+        Ok(Self { hashes, role })
+    }
+}
+
+impl AuthBackend for HtpasswdBackend {
+    fn authenticate(&self, username: &Username, password: &Password) -> io::Result<Verdict> {
+        Ok(match self.hashes.get(username) {
+            None => Verdict::UnknownUser,
+            Some(hash) if verify(hash, password.expose()) => {
+                Verdict::Authenticated(LoginAction::Accept(self.role.clone()))
+            }
+            Some(_) => Verdict::WrongPassword,
+        })
+    }
+}
+
+fn supported(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$", "{SHA}", APR1]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+}
+
+fn verify(hash: &str, password: &str) -> bool {
+    if hash.starts_with("$2") {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    } else if let Some(digest) = hash.strip_prefix("{SHA}") {
+        let sha = sha1::Sha1::digest(password.as_bytes());
+        base64::engine::general_purpose::STANDARD.encode(sha) == digest
+    } else if let Some(rest) = hash.strip_prefix(APR1) {
+        let salt = rest.split('$').next().unwrap_or_default();
+        apr1(password, salt) == hash
+    } else {
+        false
+    }
+}
+
+// The MD5 based crypt(3) variant Apache uses, with its own magic string.
+fn apr1(password: &str, salt: &str) -> String {
+    let (password, salt) = (password.as_bytes(), &salt.as_bytes()[..salt.len().min(8)]);
+
+    let alternate = Md5::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(password)
+        .finalize();
+    let mut context = Md5::new()
+        .chain_update(password)
+        .chain_update(APR1)
+        .chain_update(salt);
+    for chunk in (0..password.len()).step_by(16) {
+        context.update(&alternate[..(password.len() - chunk).min(16)]);
+    }
+    let mut length = password.len();
+    while length > 0 {
+        match length & 1 {
+            1 => context.update([0]),
+            _ => context.update(&password[..1]),
+        }
+        length >>= 1;
+    }
+    let mut digest = context.finalize();
+
+    // Deliberately slow.
+    for round in 0..1000 {
+        let mut context = Md5::new();
+        match round & 1 {
+            1 => context.update(password),
+            _ => context.update(digest),
+        }
+        if round % 3 != 0 {
+            context.update(salt);
+        }
+        if round % 7 != 0 {
+            context.update(password);
+        }
+        match round & 1 {
+            1 => context.update(digest),
+            _ => context.update(password),
+        }
+        digest = context.finalize();
+    }
+
+    let mut encoded = String::new();
+    let mut push = |value: u32, characters: usize| {
+        (0..characters)
+            .for_each(|i| encoded.push(ITOA64[(value >> (6 * i)) as usize & 0x3f] as char))
+    };
+    for [a, b, c] in [[0, 6, 12], [1, 7, 13], [2, 8, 14], [3, 9, 15], [4, 10, 5]] {
+        push(
+            (digest[a] as u32) << 16 | (digest[b] as u32) << 8 | digest[c] as u32,
+            4,
+        );
+    }
+    push(digest[11] as u32, 2);
+
+    format!("{APR1}{}${encoded}", String::from_utf8_lossy(salt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_htpasswd() {
+        // From `openssl passwd -apr1` and `htpasswd -nbs`; Apache writes bcrypt as `$2y$`.
+        let file = "# team members\n\
+                    mantou:$apr1$qHDFfhPC$nITSVHgYbDAK1Y0acGRnY0\n\
+                    baga:{SHA}VBPuJHI7uixaa6LQGWx4s+5GKNE=\n\
+                    \n\
+                    Kevin:BCRYPT\n";
+        let bcrypt = bcrypt::hash("myPassword", 4)
+            .unwrap()
+            .replacen("$2b$", "$2y$", 1);
+        let backend = HtpasswdBackend::parse(&file.replace("BCRYPT", &bcrypt), Role::User).unwrap();
+
+        for username in ["mantou", "baga", "kevin"] {
+            let username = Username::new(username).unwrap();
+            assert_eq!(
+                backend
+                    .authenticate(&username, &Password::from("myPassword"))
+                    .unwrap(),
+                Verdict::Authenticated(LoginAction::Accept(Role::User))
+            );
+            assert_eq!(
+                backend
+                    .authenticate(&username, &Password::from("mypassword"))
+                    .unwrap(),
+                Verdict::WrongPassword
+            );
+        }
+        let adam = Username::new("adam").unwrap();
+        assert_eq!(
+            backend
+                .authenticate(&adam, &Password::from("myPassword"))
+                .unwrap(),
+            Verdict::UnknownUser
+        );
+
+        for invalid in [
+            "adam",
+            "adam:plaintext",
+            "ad am:{SHA}x",
+            "a:{SHA}x\nA:{SHA}y",
+        ] {
+            assert!(
+                HtpasswdBackend::parse(invalid, Role::User).is_err(),
+                "{invalid:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,274 @@
+// Logs in by binding to an LDAP directory as the user: if the directory takes
+// the password, they're in. Only an LDAPv3 simple bind is spoken, over plain
+// TCP, so the password crosses the network as is: keep the directory on a
+// trusted network, or behind a TLS tunnel.
+use super::{AuthBackend, Verdict};
+use crate::{LoginAction, Password, Role, Username};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Far more than any bind response needs.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+// BER tags and result codes from RFC 4511.
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const ENUMERATED: u8 = 0x0a;
+const BIND_REQUEST: u8 = 0x60;
+const BIND_RESPONSE: u8 = 0x61;
+const SIMPLE_AUTHENTICATION: u8 = 0x80;
+const UNBIND_REQUEST: u8 = 0x42;
+const SUCCESS: u32 = 0;
+const INVALID_CREDENTIALS: u32 = 49;
+
+pub struct LdapBackend {
+    address: String,
+    // The user's DN with `{username}` in place of their username, e.g.
+    // `uid={username},ou=people,dc=example,dc=com`.
+    dn_template: String,
+    role: Role,
+}
+
+impl LdapBackend {
+    // Everyone the directory lets in gets `role`.
+    pub fn new(address: impl Into<String>, dn_template: impl Into<String>, role: Role) -> Self {
+        Self {
+            address: address.into(),
+            dn_template: dn_template.into(),
+            role,
+        }
+    }
+
+    // The directory's result code and diagnostic message.
+    fn bind(&self, dn: &str, password: &Password) -> io::Result<(u32, String)> {
+        let address = self.address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no address", self.address),
+            )
+        })?;
+        let mut socket = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        socket.set_read_timeout(Some(TIMEOUT))?;
+        socket.set_write_timeout(Some(TIMEOUT))?;
+
+        socket.write_all(&bind_request(1, dn, password))?;
+        let result = bind_result(&read_message(&mut socket)?)?;
+        // Only polite: the answer is already in.
+        let _ = socket.write_all(&[SEQUENCE, 5, INTEGER, 1, 2, UNBIND_REQUEST, 0]);
+        Ok(result)
+    }
+}
+
+impl AuthBackend for LdapBackend {
+    fn authenticate(&self, username: &Username, password: &Password) -> io::Result<Verdict> {
+        // A bind without a password is anonymous, and most directories allow that.
+        if password.is_empty() {
+            return Ok(Verdict::UnknownUser);
+        }
+        // Usernames are only letters, digits and `._-`: nothing that needs escaping in a DN.
+        let dn = self.dn_template.replace("{username}", username.as_str());
+        match self.bind(&dn, password)? {
+            (SUCCESS, _) => Ok(Verdict::Authenticated(LoginAction::Accept(
+                self.role.clone(),
+            ))),
+            // Directories won't say whether the user exists, so let the next backend try.
+            (INVALID_CREDENTIALS, _) => Ok(Verdict::UnknownUser),
+            (code, message) => Err(io::Error::other(format!(
+                "{dn} can't bind, result code {code}: {message}"
+            ))),
+        }
+    }
+}
+
+// The BER length of `length` bytes of content.
+fn length(length: usize) -> Vec<u8> {
+    if length < 0x80 {
+        return vec![length as u8];
+    }
+    let bytes = length.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+    let mut encoded = vec![0x80 | (bytes.len() - skip) as u8];
+    encoded.extend(&bytes[skip..]);
+    encoded
+}
+
+// Built in one buffer, sized up front, so the password is never left behind
+// in a reallocated copy.
+fn bind_request(id: u8, dn: &str, password: &Password) -> Zeroizing<Vec<u8>> {
+    let password = password.expose().as_bytes();
+    let bind = [
+        &[INTEGER, 1, 3][..],
+        &[OCTET_STRING],
+        &length(dn.len())[..],
+        dn.as_bytes(),
+        &[SIMPLE_AUTHENTICATION],
+        &length(password.len())[..],
+    ];
+    let bind_length = bind.iter().map(|part| part.len()).sum::<usize>() + password.len();
+    let message = [
+        &[INTEGER, 1, id][..],
+        &[BIND_REQUEST],
+        &length(bind_length)[..],
+    ];
+    let message_length = message.iter().map(|part| part.len()).sum::<usize>() + bind_length;
+
+    let header = [&[SEQUENCE][..], &length(message_length)];
+    let size = header.iter().map(|part| part.len()).sum::<usize>() + message_length;
+    let mut request = Zeroizing::new(Vec::with_capacity(size));
+    for part in header.iter().chain(&message).chain(&bind) {
+        request.extend_from_slice(part);
+    }
+    request.extend_from_slice(password);
+    request
+}
+
+// Reads one LDAPMessage and returns what's inside its SEQUENCE.
+fn read_message(socket: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut header = [0; 2];
+    socket.read_exact(&mut header)?;
+    let mut size = header[1] as usize;
+    if header[1] & 0x80 != 0 {
+        let mut bytes = vec![0; (header[1] & 0x7f) as usize];
+        if bytes.len() > 4 {
+            return Err(invalid("length too long"));
+        }
+        socket.read_exact(&mut bytes)?;
+        size = bytes
+            .iter()
+            .fold(0, |size, byte| size << 8 | *byte as usize);
+    }
+    if header[0] != SEQUENCE || size > MAX_MESSAGE_SIZE {
+        return Err(invalid("not an LDAP message"));
+    }
+    let mut message = vec![0; size];
+    socket.read_exact(&mut message)?;
+    Ok(message)
+}
+
+// Splits off the first element: its tag, its content, and whatever follows it.
+fn element(bytes: &[u8]) -> io::Result<(u8, &[u8], &[u8])> {
+    let truncated = || invalid("truncated message");
+    let (&tag, rest) = bytes.split_first().ok_or_else(truncated)?;
+    let (&first, mut rest) = rest.split_first().ok_or_else(truncated)?;
+    let mut size = first as usize;
+    if first & 0x80 != 0 {
+        let count = (first & 0x7f) as usize;
+        if count > 4 || rest.len() < count {
+            return Err(truncated());
+        }
+        size = rest[..count]
+            .iter()
+            .fold(0, |size, byte| size << 8 | *byte as usize);
+        rest = &rest[count..];
+    }
+    if rest.len() < size {
+        return Err(truncated());
+    }
+    Ok((tag, &rest[..size], &rest[size..]))
+}
+
+fn bind_result(message: &[u8]) -> io::Result<(u32, String)> {
+    let (INTEGER, [1], rest) = element(message)? else {
+        return Err(invalid("not the answer to our bind"));
+    };
+    let (BIND_RESPONSE, response, _) = element(rest)? else {
+        return Err(invalid("not a bind response"));
+    };
+    let (ENUMERATED, code, rest) = element(response)? else {
+        return Err(invalid("no result code"));
+    };
+    let (_, _matched_dn, rest) = element(rest)?;
+    let (_, diagnostic, _) = element(rest)?;
+    let code = code.iter().fold(0, |code, byte| code << 8 | *byte as u32);
+    Ok((code, String::from_utf8_lossy(diagnostic).into_owned()))
+}
+
+fn invalid(problem: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("LDAP: {problem}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    // A directory with one user, answering each connection's bind.
+    fn stand_in() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for socket in listener.incoming() {
+                let mut socket = socket.unwrap();
+                let message = read_message(&mut socket).unwrap();
+                let (INTEGER, [id], rest) = element(&message).unwrap() else {
+                    panic!("no message id");
+                };
+                let (BIND_REQUEST, bind, _) = element(rest).unwrap() else {
+                    panic!("not a bind");
+                };
+                let (INTEGER, [3], rest) = element(bind).unwrap() else {
+                    panic!("not LDAPv3");
+                };
+                let (_, dn, rest) = element(rest).unwrap();
+                let (_, password, _) = element(rest).unwrap();
+                let code = match (dn, password) {
+                    (b"uid=mantou,ou=people,dc=example,dc=com", b"myPassword") => SUCCESS,
+                    (b"uid=broken,ou=people,dc=example,dc=com", _) => 80,
+                    _ => INVALID_CREDENTIALS,
+                };
+                let response = [
+                    SEQUENCE,
+                    12,
+                    INTEGER,
+                    1,
+                    *id,
+                    BIND_RESPONSE,
+                    7,
+                    ENUMERATED,
+                    1,
+                    code as u8,
+                    OCTET_STRING,
+                    0,
+                    OCTET_STRING,
+                    0,
+                ];
+                socket.write_all(&response).unwrap();
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn test_ldap_bind() {
+        let backend = LdapBackend::new(
+            stand_in(),
+            "uid={username},ou=people,dc=example,dc=com",
+            Role::User,
+        );
+        let authenticate = |username: &str, password: &str| {
+            backend.authenticate(&Username::new(username).unwrap(), &Password::from(password))
+        };
+        assert_eq!(
+            authenticate("Mantou", "myPassword").unwrap(),
+            Verdict::Authenticated(LoginAction::Accept(Role::User))
+        );
+        assert_eq!(
+            authenticate("mantou", "mypassword").unwrap(),
+            Verdict::UnknownUser
+        );
+        assert_eq!(authenticate("mantou", "").unwrap(), Verdict::UnknownUser);
+        assert!(authenticate("broken", "myPassword").is_err());
+
+        // A long password needs the long form of the BER length.
+        let long = "x".repeat(300);
+        let request = bind_request(1, "uid=a", &Password::from(long.as_str()));
+        let (_, message, _) = element(&request).unwrap();
+        let (_, bind, _) = element(element(message).unwrap().2).unwrap();
+        let (_, password, _) = element(element(element(bind).unwrap().2).unwrap().2).unwrap();
+        assert_eq!(password, long.as_bytes());
+    }
+}
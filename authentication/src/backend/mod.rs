@@ -0,0 +1,160 @@
+// Places other than users.json that can vouch for a password. Each backend
+// only says what it knows about a login; `ChainBackend` asks several in turn,
+// applies group roles and turns the answer into a `LoginAction`.
+mod htpasswd;
+mod ldap;
+
+pub use htpasswd::HtpasswdBackend;
+pub use ldap::LdapBackend;
+
+use crate::{effective_action, login_hash, Groups, LoginAction, Password, User, Username};
+use std::collections::HashMap;
+use std::io;
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    // Not a user this backend can vouch for: ask the next one.
+    UnknownUser,
+    // This backend's user, with the wrong password. No other backend is asked.
+    WrongPassword,
+    Authenticated(LoginAction),
+}
+
+pub trait AuthBackend: Send + Sync {
+    // `Err` means the backend couldn't be asked at all, e.g. the directory is down.
+    fn authenticate(&self, username: &Username, password: &Password) -> io::Result<Verdict>;
+}
+
+// The native users, as loaded from users.json.
+impl AuthBackend for HashMap<Username, User> {
+    fn authenticate(&self, username: &Username, password: &Password) -> io::Result<Verdict> {
+        Ok(match self.get(username) {
+            None => Verdict::UnknownUser,
+            Some(user) if user.password == login_hash(password) => {
+                Verdict::Authenticated(user.action.clone())
+            }
+            Some(_) => Verdict::WrongPassword,
+        })
+    }
+}
+
+// Tries each backend in order until one knows the user.
+pub struct ChainBackend {
+    backends: Vec<Box<dyn AuthBackend>>,
+    groups: Groups,
+}
+
+impl ChainBackend {
+    pub fn new(backends: Vec<Box<dyn AuthBackend>>) -> Self {
+        Self {
+            backends,
+            groups: Groups::new(),
+        }
+    }
+
+    // Raise the role of whoever a backend accepts to their groups', as
+    // `login_with_groups` does.
+    pub fn with_groups(mut self, groups: Groups) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    // Like `login`: `None` for unknown users and wrong passwords alike.
+    pub fn login(&self, username: &str, password: &Password) -> io::Result<Option<LoginAction>> {
+        let Ok(username) = Username::new(username) else {
+            return Ok(None);
+        };
+        Ok(match self.authenticate(&username, password)? {
+            Verdict::Authenticated(action) => Some(action),
+            Verdict::UnknownUser | Verdict::WrongPassword => None,
+        })
+    }
+}
+
+impl AuthBackend for ChainBackend {
+    fn authenticate(&self, username: &Username, password: &Password) -> io::Result<Verdict> {
+        // A backend that is down only matters if nobody after it knows the
+        // user either: they might have been in it.
+        let mut error = None;
+        for backend in &self.backends {
+            match backend.authenticate(username, password) {
+                Ok(Verdict::UnknownUser) => {}
+                Ok(Verdict::Authenticated(action)) => {
+                    let action = effective_action(&self.groups, username, action);
+                    return Ok(Verdict::Authenticated(action));
+                }
+                Ok(verdict) => return Ok(verdict),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        error.map_or(Ok(Verdict::UnknownUser), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DeniedReason, Group, Role};
+
+    struct Down;
+
+    impl AuthBackend for Down {
+        fn authenticate(&self, _: &Username, _: &Password) -> io::Result<Verdict> {
+            Err(io::Error::new(io::ErrorKind::ConnectionRefused, "down"))
+        }
+    }
+
+    #[test]
+    fn test_chain() {
+        let password = Password::from("password");
+        let user = |name: &str, action| {
+            (
+                Username::new(name).unwrap(),
+                User::new(Username::new(name).unwrap(), &password, action),
+            )
+        };
+        let native: HashMap<Username, User> = [
+            user("adam", LoginAction::Accept(Role::Admin)),
+            user("jake", LoginAction::Denied(DeniedReason::PasswordExpired)),
+        ]
+        .into_iter()
+        .collect();
+        let htpasswd = HtpasswdBackend::parse(
+            "mike:{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=\nadam:{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=",
+            Role::Limited,
+        )
+        .unwrap();
+        let ops = Group {
+            name: Username::new("ops").unwrap(),
+            role: Role::User,
+            members: ["jake", "mike"]
+                .map(|name| Username::new(name).unwrap())
+                .into(),
+        };
+        let groups = Groups::from([(ops.name.clone(), ops)]);
+        let chain = ChainBackend::new(vec![Box::new(native), Box::new(Down), Box::new(htpasswd)])
+            .with_groups(groups);
+
+        let login = |username, password| chain.login(username, &Password::from(password)).unwrap();
+        assert_eq!(
+            login("Adam", "password"),
+            Some(LoginAction::Accept(Role::Admin))
+        );
+        assert_eq!(
+            login("jake", "password"),
+            Some(LoginAction::Denied(DeniedReason::PasswordExpired))
+        );
+        // In a group, for all that htpasswd only knows him as Limited.
+        assert_eq!(
+            login("mike", "password"),
+            Some(LoginAction::Accept(Role::User))
+        );
+        // The native store owns adam: the htpasswd entry is never consulted.
+        assert_eq!(login("adam", "wrong"), None);
+        assert_eq!(login("not a username", "password"), None);
+        // Nobody else knew kevin, so he might have been in the backend that was down.
+        assert!(chain.login("kevin", &password).is_err());
+    }
+}
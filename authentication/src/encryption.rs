@@ -0,0 +1,339 @@
+// Keeping users.json encrypted at rest. An encrypted file holds the usual
+// versioned JSON sealed with XChaCha20-Poly1305, under a key read from a key
+// file or derived from a passphrase with Argon2id:
+//
+//     {"encrypted": {"cipher": "XChaCha20-Poly1305", "kdf": ..., "nonce": ..., "ciphertext": ...}}
+//
+// `load_users` and `save_users` handle encrypted files transparently, taking
+// the key from `USERS_KEY_FILE` or `USERS_PASSPHRASE`, and keep a file in the
+// format they found it in. `userman encrypt`, `decrypt` and `rekey` convert.
+use crate::{schema, write_atomically, Password};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+pub const KEY_FILE_VAR: &str = "USERS_KEY_FILE";
+pub const PASSPHRASE_VAR: &str = "USERS_PASSPHRASE";
+const CIPHER: &str = "XChaCha20-Poly1305";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 24;
+const SALT_LENGTH: usize = 16;
+
+// Where the key comes from.
+pub enum Key {
+    // 32 random bytes, base64 encoded, as written by `create_key_file`.
+    File(PathBuf),
+    Passphrase(Password),
+}
+
+impl Key {
+    // The key file wins if both are set.
+    pub fn from_env() -> Option<Self> {
+        if let Some(path) = std::env::var_os(KEY_FILE_VAR) {
+            return Some(Self::File(path.into()));
+        }
+        std::env::var(PASSPHRASE_VAR)
+            .ok()
+            .map(|passphrase| Self::Passphrase(Password::from(passphrase)))
+    }
+
+    fn derive(&self, kdf: &Kdf) -> io::Result<Zeroizing<[u8; KEY_LENGTH]>> {
+        let mut key = Zeroizing::new([0; KEY_LENGTH]);
+        match (self, kdf) {
+            (Self::File(path), Kdf::KeyFile) => {
+                let text = Zeroizing::new(std::fs::read_to_string(path)?);
+                let bytes = Zeroizing::new(
+                    BASE64_STANDARD
+                        .decode(text.trim())
+                        .map_err(|_| invalid(format!("{} is not a key file", path.display())))?,
+                );
+                if bytes.len() != KEY_LENGTH {
+                    return Err(invalid(format!("{} is not a key file", path.display())));
+                }
+                key.copy_from_slice(&bytes);
+            }
+            (
+                Self::Passphrase(passphrase),
+                Kdf::Argon2id {
+                    salt,
+                    memory_kib,
+                    iterations,
+                    parallelism,
+                },
+            ) => {
+                let salt = BASE64_STANDARD
+                    .decode(salt)
+                    .map_err(|_| invalid("bad salt"))?;
+                let params = Params::new(*memory_kib, *iterations, *parallelism, Some(KEY_LENGTH))
+                    .map_err(|e| invalid(format!("bad Argon2 parameters: {e}")))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.expose().as_bytes(), &salt, &mut *key)
+                    .map_err(|e| invalid(format!("unable to derive the key: {e}")))?;
+            }
+            (Self::File(_), Kdf::Argon2id { .. }) => {
+                return Err(invalid("encrypted with a passphrase, not a key file"))
+            }
+            (Self::Passphrase(_), Kdf::KeyFile) => {
+                return Err(invalid("encrypted with a key file, not a passphrase"))
+            }
+        }
+        Ok(key)
+    }
+
+    // A fresh salt every time for passphrases, so no two files share a key.
+    fn new_kdf(&self) -> Kdf {
+        match self {
+            Self::File(_) => Kdf::KeyFile,
+            Self::Passphrase(_) => {
+                let mut salt = [0; SALT_LENGTH];
+                rand::thread_rng().fill_bytes(&mut salt);
+                Kdf::Argon2id {
+                    salt: BASE64_STANDARD.encode(salt),
+                    memory_kib: Params::DEFAULT_M_COST,
+                    iterations: Params::DEFAULT_T_COST,
+                    parallelism: Params::DEFAULT_P_COST,
+                }
+            }
+        }
+    }
+}
+
+// How the key was made, kept with the file so it can be made again. Its
+// parameters are authenticated along with the users.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Kdf {
+    KeyFile,
+    Argon2id {
+        salt: String,
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    cipher: String,
+    kdf: Kdf,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    encrypted: Sealed,
+}
+
+// How a users file was encrypted, or `None` for plain ones.
+pub fn kdf_of(contents: &str) -> Option<Kdf> {
+    serde_json::from_str::<EncryptedFile>(contents)
+        .ok()
+        .map(|file| file.encrypted.kdf)
+}
+
+pub fn encrypt(plaintext: &str, key: &Key) -> io::Result<String> {
+    let kdf = key.new_kdf();
+    let cipher = XChaCha20Poly1305::new((&*key.derive(&kdf)?).into());
+    let mut nonce = [0; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let aad = serde_json::to_vec(&kdf)?;
+    let payload = Payload {
+        msg: plaintext.as_bytes(),
+        aad: &aad,
+    };
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), payload)
+        .map_err(|_| invalid("unable to encrypt"))?;
+    let file = EncryptedFile {
+        encrypted: Sealed {
+            cipher: CIPHER.to_string(),
+            kdf,
+            nonce: BASE64_STANDARD.encode(nonce),
+            ciphertext: BASE64_STANDARD.encode(ciphertext),
+        },
+    };
+    Ok(serde_json::to_string_pretty(&file)?)
+}
+
+pub fn decrypt(contents: &str, key: &Key) -> io::Result<Zeroizing<String>> {
+    let EncryptedFile { encrypted: sealed } = serde_json::from_str(contents)?;
+    if sealed.cipher != CIPHER {
+        return Err(invalid(format!("unknown cipher {:?}", sealed.cipher)));
+    }
+    let nonce = BASE64_STANDARD
+        .decode(&sealed.nonce)
+        .ok()
+        .filter(|nonce| nonce.len() == NONCE_LENGTH)
+        .ok_or_else(|| invalid("bad nonce"))?;
+    let ciphertext = BASE64_STANDARD
+        .decode(&sealed.ciphertext)
+        .map_err(|_| invalid("bad ciphertext"))?;
+    let cipher = XChaCha20Poly1305::new((&*key.derive(&sealed.kdf)?).into());
+    let aad = serde_json::to_vec(&sealed.kdf)?;
+    let payload = Payload {
+        msg: &ciphertext,
+        aad: &aad,
+    };
+    let plaintext = Zeroizing::new(
+        cipher
+            .decrypt(XNonce::from_slice(&nonce), payload)
+            .map_err(|_| invalid("wrong key, or the file has been tampered with"))?,
+    );
+    let plaintext = std::str::from_utf8(&plaintext).map_err(|_| invalid("not text"))?;
+    Ok(Zeroizing::new(plaintext.to_string()))
+}
+
+// Write a new random key, readable by its owner only. Never overwrites one.
+pub fn create_key_file(path: impl AsRef<Path>) -> io::Result<()> {
+    use std::io::Write;
+    let mut key = Zeroizing::new([0; KEY_LENGTH]);
+    rand::thread_rng().fill_bytes(&mut *key);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    writeln!(
+        file,
+        "{}",
+        Zeroizing::new(BASE64_STANDARD.encode(*key)).as_str()
+    )
+}
+
+// The plain JSON of a users file, for tools that check it by hand.
+pub fn read_users_json(path: impl AsRef<Path>) -> io::Result<Zeroizing<String>> {
+    read_plaintext(path.as_ref()).map(|(json, _)| json)
+}
+
+// The plain JSON of a users file, decrypted if need be with the key from the
+// environment.
+pub(crate) fn read_plaintext(path: &Path) -> io::Result<(Zeroizing<String>, Option<Key>)> {
+    let contents = std::fs::read_to_string(path)?;
+    if kdf_of(&contents).is_none() {
+        return Ok((Zeroizing::new(contents), None));
+    }
+    let key = env_key(path)?;
+    Ok((decrypt(&contents, &key)?, Some(key)))
+}
+
+// The key to save `path` with: files that are encrypted stay encrypted.
+pub(crate) fn key_for(path: &Path) -> io::Result<Option<Key>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) if kdf_of(&contents).is_some() => Ok(Some(env_key(path)?)),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn env_key(path: &Path) -> io::Result<Key> {
+    Key::from_env().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is encrypted, set {KEY_FILE_VAR} or {PASSPHRASE_VAR}",
+                path.display()
+            ),
+        )
+    })
+}
+
+// Encrypt a plain users file in place. It is brought up to the current layout
+// on the way.
+pub fn encrypt_users_file(path: impl AsRef<Path>, key: &Key) -> io::Result<()> {
+    let path = path.as_ref();
+    let contents = Zeroizing::new(std::fs::read_to_string(path)?);
+    if kdf_of(&contents).is_some() {
+        return Err(invalid(format!("{} is already encrypted", path.display())));
+    }
+    let (users, _) = schema::decode_users(&contents)?;
+    let plaintext = Zeroizing::new(schema::encode_users(&users)?);
+    write_atomically(path, &encrypt(&plaintext, key)?)
+}
+
+pub fn decrypt_users_file(path: impl AsRef<Path>, key: &Key) -> io::Result<()> {
+    let path = path.as_ref();
+    let (users, _) = schema::decode_users(&decrypt(&std::fs::read_to_string(path)?, key)?)?;
+    write_atomically(path, &schema::encode_users(&users)?)
+}
+
+// Encrypt again under `new`, e.g. after the old passphrase got around.
+pub fn rekey_users_file(path: impl AsRef<Path>, old: &Key, new: &Key) -> io::Result<()> {
+    let path = path.as_ref();
+    let plaintext = decrypt(&std::fs::read_to_string(path)?, old)?;
+    schema::decode_users(&plaintext)?;
+    write_atomically(path, &encrypt(&plaintext, new)?)
+}
+
+fn invalid(problem: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, problem.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_users, manage, save_users, Role, User, Username};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_encryption() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let (path, key_path) = (
+            dir.join(format!("encrypted-users-{id}.json")),
+            dir.join(format!("users-{id}.key")),
+        );
+        let mut users: HashMap<Username, User> = HashMap::new();
+        let mantou = Username::new("mantou").unwrap();
+        manage::add_user(
+            &mut users,
+            &mantou,
+            &Password::from("password"),
+            Role::Admin,
+        )
+        .unwrap();
+        save_users(&path, &users).unwrap();
+
+        create_key_file(&key_path).unwrap();
+        assert!(create_key_file(&key_path).is_err());
+        let key = Key::File(key_path.clone());
+        encrypt_users_file(&path, &key).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(kdf_of(&contents), Some(Kdf::KeyFile));
+        assert!(!contents.contains("mantou"));
+        assert!(encrypt_users_file(&path, &key).is_err());
+
+        // The environment isn't touched, so this is the error callers get
+        // without a key.
+        if Key::from_env().is_none() {
+            assert!(load_users(&path).is_err());
+        }
+
+        let passphrase = Key::Passphrase(Password::from("correct horse battery staple"));
+        rekey_users_file(&path, &key, &passphrase).unwrap();
+        assert!(decrypt(&std::fs::read_to_string(&path).unwrap(), &key).is_err());
+        let wrong = Key::Passphrase(Password::from("incorrect horse"));
+        assert!(decrypt_users_file(&path, &wrong).is_err());
+
+        // Tampering with the parameters is caught too.
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let tampered = contents.replace("\"iterations\": 2", "\"iterations\": 1");
+        assert_ne!(tampered, contents);
+        assert!(decrypt(&tampered, &passphrase).is_err());
+
+        decrypt_users_file(&path, &passphrase).unwrap();
+        assert_eq!(
+            load_users(&path).unwrap()[&mantou].action,
+            users[&mantou].action
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&key_path).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+// Groups grant a role to all their members at once, e.g. everyone in `ops`
+// is an admin. A member's effective role is the highest of their own and
+// those of their groups. Groups are kept in their own file, next to users.json.
+use crate::{login, manage, write_atomically, LoginAction, Password, Role, User, Username};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Group {
+    // Group names follow the same rules as usernames.
+    pub name: Username,
+    pub role: Role,
+    pub members: BTreeSet<Username>,
+}
+
+pub type Groups = HashMap<Username, Group>;
+
+pub fn get_groups() -> Groups {
+    load_groups("groups.json").unwrap()
+}
+
+// No file just means nobody has created a group yet.
+pub fn load_groups(path: impl AsRef<Path>) -> std::io::Result<Groups> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Groups::new()),
+        Err(e) => return Err(e),
+    };
+    let groups: Vec<Group> = serde_json::from_str(&json)?;
+    Ok(groups
+        .into_iter()
+        .map(|group| (group.name.clone(), group))
+        .collect())
+}
+
+pub fn save_groups_file(groups: &Groups) {
+    save_groups("groups.json", groups).unwrap();
+}
+
+// Saved as a list sorted by name, so the file diffs well.
+pub fn save_groups(path: impl AsRef<Path>, groups: &Groups) -> std::io::Result<()> {
+    let mut groups: Vec<&Group> = groups.values().collect();
+    groups.sort_by(|a, b| a.name.cmp(&b.name));
+    write_atomically(path.as_ref(), &serde_json::to_string_pretty(&groups)?)
+}
+
+// `manage::drop_deleted_members` on the groups file, for every front end that
+// deletes users to call afterwards.
+pub fn drop_deleted_members_from(
+    path: impl AsRef<Path>,
+    users: &HashMap<Username, User>,
+) -> std::io::Result<()> {
+    let mut groups = load_groups(&path)?;
+    if manage::drop_deleted_members(&mut groups, users) {
+        save_groups(path, &groups)?;
+    }
+    Ok(())
+}
+
+// Raise an accepted user's role to the highest their groups grant. Groups
+// never let a locked or expired user in.
+pub fn effective_action(groups: &Groups, username: &Username, action: LoginAction) -> LoginAction {
+    match action {
+        LoginAction::Accept(role) => LoginAction::Accept(
+            groups
+                .values()
+                .filter(|group| group.members.contains(username))
+                .map(|group| group.role.clone())
+                .fold(role, Role::max),
+        ),
+        denied => denied,
+    }
+}
+
+// `login`, with group roles applied.
+pub fn login_with_groups(
+    users: &HashMap<Username, User>,
+    groups: &Groups,
+    username: &str,
+    password: &Password,
+) -> Option<LoginAction> {
+    let action = login(users, username, password)?;
+    Some(effective_action(
+        groups,
+        &Username::new(username).ok()?,
+        action,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manage, DeniedReason};
+
+    #[test]
+    fn test_group_roles() {
+        let password = Password::from("password");
+        let mut users = HashMap::new();
+        let mut groups = Groups::new();
+        let [ops, interns, mantou, baga] =
+            ["ops", "interns", "mantou", "baga"].map(|name| Username::new(name).unwrap());
+        manage::add_user(&mut users, &mantou, &password, Role::Limited).unwrap();
+        manage::add_user(&mut users, &baga, &password, Role::User).unwrap();
+        manage::create_group(&mut groups, &ops, Role::Admin).unwrap();
+        manage::create_group(&mut groups, &interns, Role::Limited).unwrap();
+        manage::add_member(&mut groups, &users, &interns, &baga).unwrap();
+        assert_eq!(
+            login_with_groups(&users, &groups, "baga", &password),
+            Some(LoginAction::Accept(Role::User))
+        );
+
+        manage::add_member(&mut groups, &users, &ops, &mantou).unwrap();
+        manage::add_member(&mut groups, &users, &interns, &mantou).unwrap();
+        assert_eq!(
+            login_with_groups(&users, &groups, "Mantou", &password),
+            Some(LoginAction::Accept(Role::Admin))
+        );
+        manage::expire(&mut users, &mantou).unwrap();
+        assert_eq!(
+            login_with_groups(&users, &groups, "mantou", &password),
+            Some(LoginAction::Denied(DeniedReason::PasswordExpired))
+        );
+        manage::delete_user(&mut users, &baga).unwrap();
+        assert!(manage::drop_deleted_members(&mut groups, &users));
+        assert!(groups[&interns].members.iter().eq([&mantou]));
+
+        let path = std::env::temp_dir().join(format!("groups-{}.json", std::process::id()));
+        assert_eq!(load_groups(&path).unwrap(), Groups::new());
+        save_groups(&path, &groups).unwrap();
+        assert_eq!(load_groups(&path).unwrap(), groups);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,9 +1,29 @@
 use sha2::Digest;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod backend;
+pub mod encryption;
+mod group;
 mod login_action;
+pub mod manage;
+mod password;
+mod reset;
+mod schema;
 mod user;
+mod username;
+pub use backend::AuthBackend;
+pub use group::*;
 pub use login_action::*;
+pub use manage::UserError;
+pub use password::Password;
+pub use reset::*;
+pub use schema::{decode_users, encode_users, migrate_users_file, USERS_FILE_VERSION};
 pub use user::User; // export `user` mod from top-level.
+pub use username::{Username, MAX_USERNAME_LENGTH};
 
 // If we expect all who use our lib to need Serde, we could mandate that it is added
 // to their Cargo.toml file, or we could re-export it as so:
@@ -12,21 +32,22 @@ pub mod serde {
 }
 
 pub fn build_users_file() {
-    use std::io::Write;
+    save_users("users.json", &get_users_old()).unwrap();
+}
 
-    let users = get_users_old();
-    let json = serde_json::to_string_pretty(&users).unwrap();
-    let mut f = std::fs::File::create("users.json").unwrap();
-    f.write_all(json.as_bytes()).unwrap();
+pub fn get_users() -> HashMap<Username, User> {
+    load_users("users.json").unwrap()
 }
 
-pub fn get_users() -> HashMap<String, User> {
-    let json = std::fs::read_to_string("users.json").unwrap();
-    serde_json::from_str(&json).unwrap()
+// Fallible version of `get_users`, for callers that can't just panic. Every
+// user must be filed under their own username; `userman doctor` repairs files
+// where they aren't. Files in an older layout are upgraded, see `schema`.
+pub fn load_users(path: impl AsRef<Path>) -> std::io::Result<HashMap<Username, User>> {
+    schema::load_and_upgrade(path.as_ref())
 }
 
 #[allow(dead_code)]
-pub fn get_users_old() -> HashMap<String, User> {
+pub fn get_users_old() -> HashMap<Username, User> {
     /*let mut result = HashMap::new();
     result.insert(
         "adam".to_string(),
@@ -35,16 +56,24 @@ pub fn get_users_old() -> HashMap<String, User> {
     result*/
 
     let mut users = vec![
-        User::new("adam", "password", LoginAction::Accept(Role::Admin)),
-        User::new("mike", "password", LoginAction::Accept(Role::User)),
         User::new(
-            "jake",
-            "password",
+            Username::new("adam").unwrap(),
+            &Password::from("password"),
+            LoginAction::Accept(Role::Admin),
+        ),
+        User::new(
+            Username::new("mike").unwrap(),
+            &Password::from("password"),
+            LoginAction::Accept(Role::User),
+        ),
+        User::new(
+            Username::new("jake").unwrap(),
+            &Password::from("password"),
             LoginAction::Denied(DeniedReason::PasswordExpired),
         ),
         User::new(
-            "kevin",
-            "password",
+            Username::new("kevin").unwrap(),
+            &Password::from("password"),
             LoginAction::Denied(DeniedReason::AccountLocked {
                 reason: "Contact HR!".to_string(),
             }),
@@ -62,16 +91,15 @@ pub fn get_users_old() -> HashMap<String, User> {
         .collect()
 }
 
-pub fn login(users: &HashMap<String, User>, username: &str, password: &str) -> Option<LoginAction> {
+pub fn login(
+    users: &HashMap<Username, User>,
+    username: &str,
+    password: &Password,
+) -> Option<LoginAction> {
     // Option is a type that either does or doesn't have a value.
     // Its the closes thing to NULL in safe Rust.
-    let username = username.trim().to_lowercase();
-    let password = hash_password(password.trim());
-
-    users
-        .get(&username) // Returns the Option<User>
-        .filter(|user| user.password == password) // Only keep Some(user) if the password matches.
-        .map(|user| user.action.clone()) // Transform Some(user
+    let username = Username::new(username).ok()?; // Not even a valid username: nobody to log in.
+    check_login(users, &username, &login_hash(password))
 
     // Replaces:
     /*if let Some(user) = users.get(&username) {
@@ -101,45 +129,236 @@ pub fn login(users: &HashMap<String, User>, username: &str, password: &str) -> O
     }*/
 }
 
-pub fn hash_password(password: &str) -> String {
+// `login` split in two, so the async API can hash on another thread.
+pub(crate) fn login_hash(password: &Password) -> String {
+    sha256_hex(password.expose().trim())
+}
+
+pub(crate) fn check_login(
+    users: &HashMap<Username, User>,
+    username: &Username,
+    password_hash: &str,
+) -> Option<LoginAction> {
+    users
+        .get(username) // Returns the Option<User>
+        .filter(|user| user.password == password_hash) // Only keep Some(user) if the password matches.
+        .map(|user| user.action.clone()) // Transform Some(user)
+}
+
+pub fn hash_password(password: &Password) -> String {
+    sha256_hex(password.expose())
+}
+
+pub(crate) fn sha256_hex(text: &str) -> String {
     let mut hasher = sha2::Sha256::new();
-    hasher.update(password);
+    hasher.update(text);
     format!("{:X}", hasher.finalize()) // `{:X}` means printing in hexadecimal. Prod system would want to add salt.
 }
 
-pub fn save_users_file(users: &HashMap<String, User>) {
-    use std::io::Write;
-    let json = serde_json::to_string_pretty(&users).unwrap();
-    let mut f = std::fs::File::create("users.json").unwrap();
-    f.write_all(json.as_bytes()).unwrap();
+// Whether `hash` looks like something `hash_password` produced.
+pub fn is_password_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| matches!(c, '0'..='9' | 'A'..='F'))
+}
+
+pub fn save_users_file(users: &HashMap<Username, User>) {
+    save_users("users.json", users).unwrap();
+}
+
+// An encrypted file stays encrypted, see `encryption`.
+pub fn save_users(path: impl AsRef<Path>, users: &HashMap<Username, User>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let key = encryption::key_for(path)?;
+    schema::write_users(path, users, key.as_ref())
+}
+
+// Change the users file as it is now, rather than saving a copy loaded
+// earlier, which would undo whatever others saved since. The file is read
+// afresh and only written back if `change` succeeds; updates from this process
+// take turns. Callers keeping a copy of the users can take one in `change`.
+pub fn update_users_file<T>(
+    path: impl AsRef<Path>,
+    change: impl FnOnce(&mut HashMap<Username, User>) -> Result<T, UserError>,
+) -> std::io::Result<Result<T, UserError>> {
+    static UPDATES: Mutex<()> = Mutex::new(());
+    let _turn = UPDATES.lock().unwrap_or_else(PoisonError::into_inner);
+    let path = path.as_ref();
+    // A missing file is a fresh install. Checked first, as a missing key file
+    // is an error like any other.
+    let mut users = match std::fs::metadata(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+        _ => load_users(path)?,
+    };
+    let result = match change(&mut users) {
+        Ok(result) => result,
+        Err(e) => return Ok(Err(e)),
+    };
+    save_users(path, &users)?;
+    Ok(Ok(result))
+}
+
+// How times are kept in the users and tokens files.
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// Write to a temporary file first and rename it over the original, so a crash
+// halfway through never leaves a truncated file behind. Each write gets its
+// own temporary file, so concurrent writers can't rename each other's halves.
+pub(crate) fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
 }
 
 #[cfg(test)] // Only compile next section for tests.
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test] // Mark the function as a test to add it to Cargo's unit-test runner.
     fn test_enums() {
-        let users = get_users();
+        // Built in memory rather than read from users.json, so the result
+        // doesn't depend on where the tests run.
+        let users = get_users_old();
         assert_eq!(
-            login(&users, "Adam", "password"),
+            login(&users, "Adam", &Password::from("password")),
             Some(LoginAction::Accept(Role::Admin))
         );
         assert_eq!(
-            login(&users, "mike", "password"),
+            login(&users, "mike", &Password::from("password")),
             Some(LoginAction::Accept(Role::User))
         );
         assert_eq!(
-            login(&users, "jake", "password"),
+            login(&users, "jake", &Password::from("password")),
             Some(LoginAction::Denied(DeniedReason::PasswordExpired))
         );
-        assert_eq!(login(&users, "anonymous", "none"), None);
+        assert_eq!(login(&users, "anonymous", &Password::from("none")), None);
         if let Some(LoginAction::Denied(DeniedReason::AccountLocked { reason: _ })) =
-            login(&users, "kevin", "password")
+            login(&users, "kevin", &Password::from("password"))
         {
             // Everything OK
         } else {
             panic!("Failed to read kevin");
         }
     }
+
+    fn role() -> impl Strategy<Value = Role> {
+        prop_oneof![Just(Role::Admin), Just(Role::User), Just(Role::Limited)]
+    }
+
+    fn denied_reason() -> impl Strategy<Value = DeniedReason> {
+        prop_oneof![
+            Just(DeniedReason::PasswordExpired),
+            Just(DeniedReason::PendingApproval),
+            any::<String>().prop_map(|reason| DeniedReason::AccountLocked { reason }),
+        ]
+    }
+
+    fn login_action() -> impl Strategy<Value = LoginAction> {
+        prop_oneof![
+            role().prop_map(LoginAction::Accept),
+            denied_reason().prop_map(LoginAction::Denied),
+        ]
+    }
+
+    // Any letters, not just ASCII, so normalization gets exercised too.
+    fn username() -> impl Strategy<Value = Username> {
+        r"[\w.-]{1,32}".prop_filter_map("not a valid username", |name| Username::new(&name).ok())
+    }
+
+    fn user() -> impl Strategy<Value = User> {
+        (
+            username(),
+            "[0-9A-F]{64}",
+            login_action(),
+            proptest::option::of(any::<String>()),
+            proptest::option::of("[a-z]{1,8}@[a-z]{1,8}\\.com"),
+            proptest::option::of(any::<u64>()),
+            proptest::option::of(any::<u64>()),
+            proptest::option::of(role()),
+        )
+            .prop_map(
+                |(
+                    username,
+                    hash,
+                    action,
+                    display_name,
+                    email,
+                    created,
+                    last_login,
+                    previous_role,
+                )| {
+                    User {
+                        display_name,
+                        email,
+                        created,
+                        last_login,
+                        previous_role,
+                        ..User::with_hash(username, &hash, action)
+                    }
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn test_serde_round_trips(user in user(), action in login_action(), reason in denied_reason()) {
+            let json: User = serde_json::from_str(&serde_json::to_string(&user).unwrap()).unwrap();
+            prop_assert_eq!(&json, &user);
+            let binary: User = bincode::deserialize(&bincode::serialize(&user).unwrap()).unwrap();
+            prop_assert_eq!(&binary, &user);
+
+            let json: LoginAction = serde_json::from_str(&serde_json::to_string(&action).unwrap()).unwrap();
+            prop_assert_eq!(&json, &action);
+            let binary: LoginAction = bincode::deserialize(&bincode::serialize(&action).unwrap()).unwrap();
+            prop_assert_eq!(&binary, &action);
+
+            let json: DeniedReason = serde_json::from_str(&serde_json::to_string(&reason).unwrap()).unwrap();
+            prop_assert_eq!(&json, &reason);
+            let binary: DeniedReason = bincode::deserialize(&bincode::serialize(&reason).unwrap()).unwrap();
+            prop_assert_eq!(&binary, &reason);
+        }
+
+        #[test]
+        fn test_users_file_round_trip(users in proptest::collection::vec(user(), 0..8)) {
+            let users: HashMap<Username, User> =
+                users.into_iter().map(|user| (user.username.clone(), user)).collect();
+            let (decoded, version) = decode_users(&encode_users(&users).unwrap()).unwrap();
+            prop_assert_eq!(version, USERS_FILE_VERSION);
+            prop_assert_eq!(decoded, users);
+        }
+
+        // Usernames are found whatever their case and surrounding whitespace,
+        // and passwords are trimmed (the newline typed after them, say), but
+        // otherwise must match exactly.
+        #[test]
+        fn test_login_trim_and_case(
+            name in "[a-z0-9._-]{1,32}",
+            password in "[!-~]{8,20}",
+            padding in "[ \t]{0,3}",
+            action in login_action(),
+        ) {
+            let username = Username::new(&name).unwrap();
+            let user = User::new(username.clone(), &Password::from(password.as_str()), action.clone());
+            let users = HashMap::from([(username, user)]);
+            let login = |username: &str, password: &str| login(&users, username, &Password::from(password));
+
+            for name in [name.clone(), name.to_uppercase(), format!("{padding}{name}{padding}")] {
+                prop_assert_eq!(login(&name, &password), Some(action.clone()));
+            }
+            prop_assert_eq!(login(&name, &format!("{padding}{password}\n")), Some(action.clone()));
+            prop_assert_eq!(login(&name, &format!("{password}x")), None);
+            prop_assert_eq!(login(&name, &password.to_lowercase()).is_some(), password == password.to_lowercase());
+            prop_assert_eq!(login(&format!("{name}x"), &password), None);
+        }
+    }
 }
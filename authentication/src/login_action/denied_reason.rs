@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum DeniedReason {
     PasswordExpired,
     AccountLocked { reason: String }, // We can attach variables to individual entries.
+    // Registered themselves, and an admin hasn't approved them yet.
+    PendingApproval,
 }
@@ -2,6 +2,7 @@ use crate::{DeniedReason, Role}; // super:: would also work.
 use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum LoginAction {
     Accept(Role),
     Denied(DeniedReason),
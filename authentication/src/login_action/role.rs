@@ -1,8 +1,33 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum Role {
     Admin,
     User,
     Limited,
 }
+
+impl Role {
+    fn privilege(&self) -> u8 {
+        match self {
+            Self::Limited => 0,
+            Self::User => 1,
+            Self::Admin => 2,
+        }
+    }
+}
+
+// Ordered by what the role allows, not by declaration: `Limited < User < Admin`,
+// so the highest of several roles is simply their `max`.
+impl Ord for Role {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.privilege().cmp(&other.privilege())
+    }
+}
+
+impl PartialOrd for Role {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
@@ -0,0 +1,510 @@
+// User management operations shared by every admin front end (`userman`, the
+// web admin API, ...). They only touch the in-memory map: callers decide when
+// to persist it.
+use crate::{
+    hash_password, unix_seconds, DeniedReason, Group, Groups, LoginAction, Password, Role, User,
+    Username,
+};
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum UserError {
+    AlreadyExists(String),
+    NotFound(String),
+    InvalidUsername(String),
+    WeakPassword,
+    // A denied user has no role to change until they are activated again.
+    NotActive(String),
+    GroupAlreadyExists(String),
+    GroupNotFound(String),
+    AlreadyMember { username: String, group: String },
+    NotMember { username: String, group: String },
+    NotPending(String),
+    InvalidResetToken,
+    InvalidEmail(String),
+    // Denied before their role was kept, so `activate` needs to be told one.
+    NoPreviousRole(String),
+}
+
+impl std::fmt::Display for UserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyExists(username) => write!(f, "{username} already exists"),
+            Self::NotFound(username) => write!(f, "{username} doesn't exist"),
+            Self::InvalidUsername(username) => write!(f, "{username:?} is not a valid username"),
+            Self::WeakPassword => write!(
+                f,
+                "passwords must be at least {MIN_PASSWORD_LENGTH} characters long"
+            ),
+            Self::NotActive(username) => {
+                write!(f, "{username} is denied access, activate them first")
+            }
+            Self::GroupAlreadyExists(group) => write!(f, "the group {group} already exists"),
+            Self::GroupNotFound(group) => write!(f, "the group {group} doesn't exist"),
+            Self::AlreadyMember { username, group } => {
+                write!(f, "{username} is already in {group}")
+            }
+            Self::NotMember { username, group } => write!(f, "{username} isn't in {group}"),
+            Self::NotPending(username) => write!(f, "{username} isn't waiting for approval"),
+            Self::InvalidResetToken => write!(f, "the reset link is invalid or has expired"),
+            Self::InvalidEmail(email) => write!(f, "{email:?} is not a valid email address"),
+            Self::NoPreviousRole(username) => {
+                write!(
+                    f,
+                    "{username} has no earlier role to go back to, give them one"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for UserError {}
+
+// For front ends checking input before they need the `Username` itself.
+pub fn validate_username(username: &str) -> Result<(), UserError> {
+    Username::new(username).map(|_| ())
+}
+
+pub fn validate_password(password: &Password) -> Result<(), UserError> {
+    if password.len() < MIN_PASSWORD_LENGTH {
+        return Err(UserError::WeakPassword);
+    }
+    Ok(())
+}
+
+// Only catches typos: whether mail arrives is for the mail server to say.
+pub fn validate_email(email: &str) -> Result<(), UserError> {
+    match email.split_once('@') {
+        Some((local, domain))
+            if !local.is_empty()
+                && domain.contains('.')
+                && !domain.contains('@')
+                && !email.contains(char::is_whitespace) =>
+        {
+            Ok(())
+        }
+        _ => Err(UserError::InvalidEmail(email.to_string())),
+    }
+}
+
+pub fn add_user(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    password: &Password,
+    role: Role,
+) -> Result<(), UserError> {
+    validate_password(password)?;
+    if users.contains_key(username) {
+        return Err(UserError::AlreadyExists(username.to_string()));
+    }
+    let user = User::new(username.clone(), password, LoginAction::Accept(role));
+    users.insert(username.clone(), user);
+    Ok(())
+}
+
+// Self-service sign up: the new user can't log in until an admin approves them.
+pub fn register(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    password: &Password,
+) -> Result<(), UserError> {
+    add_user(users, username, password, Role::Limited)?;
+    set_action(
+        users,
+        username,
+        LoginAction::Denied(DeniedReason::PendingApproval),
+    )
+}
+
+// Let a self-registered user in, with `role`.
+pub fn approve(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    role: Role,
+) -> Result<(), UserError> {
+    if find_user(users, username)?.action != LoginAction::Denied(DeniedReason::PendingApproval) {
+        return Err(UserError::NotPending(username.to_string()));
+    }
+    set_action(users, username, LoginAction::Accept(role))
+}
+
+pub fn delete_user(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+) -> Result<User, UserError> {
+    users
+        .remove(username)
+        .ok_or_else(|| UserError::NotFound(username.to_string()))
+}
+
+pub fn change_password(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    new_password: &Password,
+) -> Result<(), UserError> {
+    validate_password(new_password)?;
+    let user = find_user(users, username)?;
+    user.password = hash_password(new_password);
+    Ok(())
+}
+
+// A password reset by the user themselves, with a reset token: it also ends
+// an expiry, giving back the role they had, but never a lock or a pending
+// approval. Expired before their role was kept, they come back `Limited`.
+pub fn reset_password(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    new_password: &Password,
+) -> Result<(), UserError> {
+    change_password(users, username, new_password)?;
+    let user = find_user(users, username)?;
+    if user.action == LoginAction::Denied(DeniedReason::PasswordExpired) {
+        let role = user.previous_role.clone().unwrap_or(Role::Limited);
+        set_action(users, username, LoginAction::Accept(role))?;
+    }
+    Ok(())
+}
+
+// Denying an active user keeps their role, for `activate` to give back.
+pub fn set_action(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    action: LoginAction,
+) -> Result<(), UserError> {
+    let user = find_user(users, username)?;
+    match (&user.action, &action) {
+        (LoginAction::Accept(role), LoginAction::Denied(_)) => {
+            user.previous_role = Some(role.clone())
+        }
+        (_, LoginAction::Accept(_)) => user.previous_role = None,
+        (LoginAction::Denied(_), LoginAction::Denied(_)) => {}
+    }
+    user.action = action;
+    Ok(())
+}
+
+pub fn set_role(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    role: Role,
+) -> Result<(), UserError> {
+    let user = find_user(users, username)?;
+    match user.action {
+        LoginAction::Accept(_) => {
+            user.action = LoginAction::Accept(role);
+            Ok(())
+        }
+        LoginAction::Denied(_) => Err(UserError::NotActive(username.to_string())),
+    }
+}
+
+pub fn lock(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    reason: &str,
+) -> Result<(), UserError> {
+    let reason = reason.to_string();
+    set_action(
+        users,
+        username,
+        LoginAction::Denied(DeniedReason::AccountLocked { reason }),
+    )
+}
+
+pub fn expire(users: &mut HashMap<Username, User>, username: &Username) -> Result<(), UserError> {
+    set_action(
+        users,
+        username,
+        LoginAction::Denied(DeniedReason::PasswordExpired),
+    )
+}
+
+// Lift any denial, letting the user in again with `role`, or if `None` with
+// the role they had before they were denied.
+pub fn activate(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    role: Option<Role>,
+) -> Result<(), UserError> {
+    let user = find_user(users, username)?;
+    let role = match (role, &user.action) {
+        (Some(role), _) => role,
+        (None, LoginAction::Accept(_)) => return Ok(()),
+        (None, LoginAction::Denied(_)) => user
+            .previous_role
+            .clone()
+            .ok_or_else(|| UserError::NoPreviousRole(username.to_string()))?,
+    };
+    set_action(users, username, LoginAction::Accept(role))
+}
+
+// `None` leaves a field as it is, an empty string clears it.
+pub fn set_profile(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    display_name: Option<&str>,
+    email: Option<&str>,
+) -> Result<(), UserError> {
+    let email = email.map(str::trim);
+    if let Some(email) = email.filter(|email| !email.is_empty()) {
+        validate_email(email)?;
+    }
+    let user = find_user(users, username)?;
+    let cleared = |value: &str| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+    if let Some(display_name) = display_name {
+        user.display_name = cleared(display_name);
+    }
+    if let Some(email) = email {
+        user.email = cleared(email);
+    }
+    Ok(())
+}
+
+// Called by front ends on every accepted login.
+pub fn record_login(
+    users: &mut HashMap<Username, User>,
+    username: &Username,
+    now: SystemTime,
+) -> Result<(), UserError> {
+    find_user(users, username)?.last_login = Some(unix_seconds(now));
+    Ok(())
+}
+
+pub fn create_group(groups: &mut Groups, name: &Username, role: Role) -> Result<(), UserError> {
+    if groups.contains_key(name) {
+        return Err(UserError::GroupAlreadyExists(name.to_string()));
+    }
+    let group = Group {
+        name: name.clone(),
+        role,
+        members: Default::default(),
+    };
+    groups.insert(name.clone(), group);
+    Ok(())
+}
+
+// Only existing users can join a group.
+pub fn add_member(
+    groups: &mut Groups,
+    users: &HashMap<Username, User>,
+    group: &Username,
+    username: &Username,
+) -> Result<(), UserError> {
+    if !users.contains_key(username) {
+        return Err(UserError::NotFound(username.to_string()));
+    }
+    if !find_group(groups, group)?.members.insert(username.clone()) {
+        return Err(UserError::AlreadyMember {
+            username: username.to_string(),
+            group: group.to_string(),
+        });
+    }
+    Ok(())
+}
+
+pub fn remove_member(
+    groups: &mut Groups,
+    group: &Username,
+    username: &Username,
+) -> Result<(), UserError> {
+    if !find_group(groups, group)?.members.remove(username) {
+        return Err(UserError::NotMember {
+            username: username.to_string(),
+            group: group.to_string(),
+        });
+    }
+    Ok(())
+}
+
+// Take deleted users out of their groups, so that someone who later gets
+// their username doesn't inherit them. Returns whether anything changed.
+pub fn drop_deleted_members(groups: &mut Groups, users: &HashMap<Username, User>) -> bool {
+    let mut changed = false;
+    for group in groups.values_mut() {
+        let before = group.members.len();
+        group.members.retain(|member| users.contains_key(member));
+        changed |= group.members.len() != before;
+    }
+    changed
+}
+
+fn find_group<'a>(groups: &'a mut Groups, name: &Username) -> Result<&'a mut Group, UserError> {
+    groups
+        .get_mut(name)
+        .ok_or_else(|| UserError::GroupNotFound(name.to_string()))
+}
+
+fn find_user<'a>(
+    users: &'a mut HashMap<Username, User>,
+    username: &Username,
+) -> Result<&'a mut User, UserError> {
+    users
+        .get_mut(username)
+        .ok_or_else(|| UserError::NotFound(username.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::login;
+
+    #[test]
+    fn test_manage_users() {
+        let mut users = HashMap::new();
+        let mantou = Username::new("mantou").unwrap();
+        add_user(
+            &mut users,
+            &mantou,
+            &Password::from("password"),
+            Role::Limited,
+        )
+        .unwrap();
+        assert_eq!(
+            add_user(&mut users, &mantou, &Password::from("password"), Role::User),
+            Err(UserError::AlreadyExists("mantou".to_string()))
+        );
+        assert_eq!(
+            validate_username("ba ga"),
+            Err(UserError::InvalidUsername("ba ga".to_string()))
+        );
+        assert_eq!(
+            add_user(&mut users, &mantou, &Password::from("baga"), Role::User),
+            Err(UserError::WeakPassword)
+        );
+        assert_eq!(
+            login(&users, " Mantou ", &Password::from("password")),
+            Some(LoginAction::Accept(Role::Limited))
+        );
+
+        change_password(&mut users, &mantou, &Password::from("new password")).unwrap();
+        let debug = format!("{:?}", users[&mantou]);
+        assert!(!debug.contains(users[&mantou].password_hash()));
+        assert_eq!(login(&users, "mantou", &Password::from("password")), None);
+
+        let locked = LoginAction::Denied(DeniedReason::AccountLocked {
+            reason: "Contact HR!".to_string(),
+        });
+        set_action(&mut users, &mantou, locked.clone()).unwrap();
+        assert_eq!(
+            login(&users, "mantou", &Password::from("new password")),
+            Some(locked)
+        );
+
+        delete_user(&mut users, &mantou).unwrap();
+        assert_eq!(
+            delete_user(&mut users, &mantou).map(|_| ()),
+            Err(UserError::NotFound("mantou".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_roles_and_denials() {
+        let mut users = HashMap::new();
+        let mantou = Username::new("mantou").unwrap();
+        let password = Password::from("password");
+        add_user(&mut users, &mantou, &password, Role::Limited).unwrap();
+        let locked = LoginAction::Denied(DeniedReason::AccountLocked {
+            reason: "Contact HR!".to_string(),
+        });
+        lock(&mut users, &mantou, "Contact HR!").unwrap();
+        assert_eq!(login(&users, "mantou", &password), Some(locked.clone()));
+
+        assert_eq!(
+            set_role(&mut users, &mantou, Role::Admin),
+            Err(UserError::NotActive("mantou".to_string()))
+        );
+        activate(&mut users, &mantou, Some(Role::User)).unwrap();
+        set_role(&mut users, &mantou, Role::Admin).unwrap();
+        assert_eq!(
+            login(&users, "mantou", &password),
+            Some(LoginAction::Accept(Role::Admin))
+        );
+        expire(&mut users, &mantou).unwrap();
+        assert_eq!(
+            login(&users, "mantou", &password),
+            Some(LoginAction::Denied(DeniedReason::PasswordExpired))
+        );
+        lock(&mut users, &mantou, "Contact HR!").unwrap();
+        assert_eq!(login(&users, "mantou", &password), Some(locked));
+
+        // However many denials later, their role comes back.
+        activate(&mut users, &mantou, None).unwrap();
+        assert_eq!(users[&mantou].action, LoginAction::Accept(Role::Admin));
+        assert_eq!(users[&mantou].previous_role, None);
+        // Unless they were denied before roles were kept.
+        users.get_mut(&mantou).unwrap().action = LoginAction::Denied(DeniedReason::PasswordExpired);
+        assert_eq!(
+            activate(&mut users, &mantou, None),
+            Err(UserError::NoPreviousRole("mantou".to_string()))
+        );
+        activate(&mut users, &mantou, Some(Role::Limited)).unwrap();
+        assert_eq!(users[&mantou].action, LoginAction::Accept(Role::Limited));
+    }
+
+    #[test]
+    fn test_register_and_approve() {
+        let mut users = HashMap::new();
+        let mantou = Username::new("mantou").unwrap();
+        let password = Password::from("password");
+        register(&mut users, &mantou, &password).unwrap();
+        assert_eq!(
+            login(&users, "mantou", &password),
+            Some(LoginAction::Denied(DeniedReason::PendingApproval))
+        );
+        assert_eq!(
+            register(&mut users, &mantou, &password),
+            Err(UserError::AlreadyExists("mantou".to_string()))
+        );
+        approve(&mut users, &mantou, Role::Limited).unwrap();
+        assert_eq!(
+            login(&users, "mantou", &password),
+            Some(LoginAction::Accept(Role::Limited))
+        );
+        assert_eq!(
+            approve(&mut users, &mantou, Role::Limited),
+            Err(UserError::NotPending("mantou".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_profile_and_login_time() {
+        let mut users = HashMap::new();
+        let mantou = Username::new("mantou").unwrap();
+        add_user(&mut users, &mantou, &Password::from("password"), Role::User).unwrap();
+        assert!(users[&mantou].created.is_some());
+        assert_eq!(users[&mantou].last_login, None);
+        let now = SystemTime::now();
+        record_login(&mut users, &mantou, now).unwrap();
+        assert_eq!(users[&mantou].last_login, Some(unix_seconds(now)));
+        let nobody = Username::new("nobody").unwrap();
+        assert_eq!(
+            record_login(&mut users, &nobody, now),
+            Err(UserError::NotFound("nobody".to_string()))
+        );
+
+        set_profile(
+            &mut users,
+            &mantou,
+            Some(" Mantou "),
+            Some("mantou@example.com"),
+        )
+        .unwrap();
+        assert_eq!(
+            set_profile(&mut users, &mantou, None, Some("mantou@")),
+            Err(UserError::InvalidEmail("mantou@".to_string()))
+        );
+        set_profile(&mut users, &mantou, None, Some("")).unwrap();
+        assert_eq!(users[&mantou].display_name.as_deref(), Some("Mantou"));
+        assert_eq!(users[&mantou].email, None);
+
+        // Files from before the profile fields still load.
+        let old: User = serde_json::from_str(
+            r#"{"username": "adam", "password": "", "action": {"Accept": "Admin"}}"#,
+        )
+        .unwrap();
+        assert_eq!((old.created, old.last_login), (None, None));
+    }
+}
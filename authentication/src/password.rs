@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+// A plaintext password. The memory is wiped when it is dropped, and it never
+// shows up in `Debug` or `Display` output, so it can't leak into logs.
+#[derive(Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "openapi", schema(value_type = String, format = Password))]
+pub struct Password(String);
+
+impl Password {
+    // The only way to read the password: grep for it to find every use.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.chars().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for Password {
+    fn from(password: String) -> Self {
+        Self(password)
+    }
+}
+
+impl From<&str> for Password {
+    fn from(password: &str) -> Self {
+        Self(password.to_string())
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Password(<redacted>)")
+    }
+}
+
+impl std::fmt::Display for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_redacted() {
+        let password = Password::from("hunter22");
+        assert_eq!(password.expose(), "hunter22");
+        assert_eq!(password.len(), 8);
+        assert!(!format!("{password:?} {password}").contains("hunter22"));
+
+        let json = serde_json::to_string(&password).unwrap();
+        assert_eq!(json, "\"hunter22\"");
+        assert_eq!(serde_json::from_str::<Password>(&json).unwrap(), password);
+    }
+}
@@ -0,0 +1,184 @@
+// Password reset tokens, for users who forgot their password or let it
+// expire. An admin issues a token, a `Notifier` delivers the link out of
+// band, and the user trades the token for a new password. Tokens are single
+// use, expire after `RESET_TOKEN_TTL`, and only their hash is stored: reading
+// the tokens file doesn't let anyone reset a password.
+use crate::{sha256_hex, unix_seconds, write_atomically, User, UserError, Username};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+pub const RESET_TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResetToken {
+    pub username: Username,
+    token_hash: String,
+    // Seconds since the Unix epoch.
+    pub expires: u64,
+}
+
+pub fn load_reset_tokens(path: impl AsRef<Path>) -> std::io::Result<Vec<ResetToken>> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn save_reset_tokens(path: impl AsRef<Path>, tokens: &[ResetToken]) -> std::io::Result<()> {
+    write_atomically(path.as_ref(), &serde_json::to_string_pretty(tokens)?)
+}
+
+// Returns the token itself, which is never stored. Any earlier token of the
+// user stops working.
+pub fn issue_reset_token(
+    tokens: &mut Vec<ResetToken>,
+    users: &HashMap<Username, User>,
+    username: &Username,
+    now: SystemTime,
+) -> Result<String, UserError> {
+    if !users.contains_key(username) {
+        return Err(UserError::NotFound(username.to_string()));
+    }
+    let now = unix_seconds(now);
+    tokens.retain(|token| token.expires > now && token.username != *username);
+
+    use rand::RngCore;
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    tokens.push(ResetToken {
+        username: username.clone(),
+        token_hash: sha256_hex(&token),
+        expires: now + RESET_TOKEN_TTL.as_secs(),
+    });
+    Ok(token)
+}
+
+// Use up `token`, returning whose password it resets. Only hashes are
+// compared, so timing says nothing about the stored tokens.
+pub fn take_reset_token(
+    tokens: &mut Vec<ResetToken>,
+    token: &str,
+    now: SystemTime,
+) -> Result<Username, UserError> {
+    let now = unix_seconds(now);
+    tokens.retain(|token| token.expires > now);
+    let hash = sha256_hex(token.trim());
+    match tokens.iter().position(|token| token.token_hash == hash) {
+        Some(index) => Ok(tokens.remove(index).username),
+        None => Err(UserError::InvalidResetToken),
+    }
+}
+
+// Where the user can redeem `token`. It goes in the fragment, which browsers
+// never send to servers, so it stays out of access logs and `Referer` headers.
+pub fn reset_link(base_url: &str, token: &str) -> String {
+    format!("{}/reset#token={token}", base_url.trim_end_matches('/'))
+}
+
+// Gets a reset link to the user, by some channel other than the one they
+// can't log in to.
+pub trait Notifier: Send + Sync {
+    fn send_reset_link(&self, username: &Username, link: &str) -> std::io::Result<()>;
+}
+
+// For an admin to pass on by hand.
+pub struct StdoutNotifier;
+
+impl Notifier for StdoutNotifier {
+    fn send_reset_link(&self, username: &Username, link: &str) -> std::io::Result<()> {
+        println!("Password reset link for {username}: {link}");
+        Ok(())
+    }
+}
+
+// Appends `username link` lines, for a mailer to pick up, or for tests.
+pub struct FileNotifier {
+    path: PathBuf,
+}
+
+impl FileNotifier {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Notifier for FileNotifier {
+    fn send_reset_link(&self, username: &Username, link: &str) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{username} {link}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{login, manage, DeniedReason, LoginAction, Password, Role};
+
+    #[test]
+    fn test_reset_tokens() {
+        let mut users = HashMap::new();
+        let mantou = Username::new("mantou").unwrap();
+        manage::add_user(
+            &mut users,
+            &mantou,
+            &Password::from("password"),
+            Role::Admin,
+        )
+        .unwrap();
+        manage::expire(&mut users, &mantou).unwrap();
+        let now = SystemTime::now();
+        let mut tokens = Vec::new();
+
+        let first = issue_reset_token(&mut tokens, &users, &mantou, now).unwrap();
+        let token = issue_reset_token(&mut tokens, &users, &mantou, now).unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert!(!serde_json::to_string(&tokens).unwrap().contains(&token));
+        assert_eq!(
+            take_reset_token(&mut tokens, &first, now),
+            Err(UserError::InvalidResetToken)
+        );
+        let unknown = Username::new("nobody").unwrap();
+        assert!(issue_reset_token(&mut tokens, &users, &unknown, now).is_err());
+
+        // Single use, and it lifts the expiry, role and all.
+        let username = take_reset_token(&mut tokens, &token, now).unwrap();
+        assert!(take_reset_token(&mut tokens, &token, now).is_err());
+        manage::reset_password(&mut users, &username, &Password::from("new password")).unwrap();
+        assert_eq!(
+            login(&users, "mantou", &Password::from("new password")),
+            Some(LoginAction::Accept(Role::Admin))
+        );
+
+        // Time limited.
+        let token = issue_reset_token(&mut tokens, &users, &mantou, now).unwrap();
+        let later = now + RESET_TOKEN_TTL + Duration::from_secs(1);
+        assert!(take_reset_token(&mut tokens, &token, later).is_err());
+        assert!(tokens.is_empty());
+
+        // Resetting doesn't lift a lock.
+        manage::lock(&mut users, &mantou, "Contact HR!").unwrap();
+        manage::reset_password(&mut users, &mantou, &Password::from("other password")).unwrap();
+        assert!(matches!(
+            login(&users, "mantou", &Password::from("other password")),
+            Some(LoginAction::Denied(DeniedReason::AccountLocked { .. }))
+        ));
+
+        let path = std::env::temp_dir().join(format!("reset-links-{}.txt", std::process::id()));
+        FileNotifier::new(&path)
+            .send_reset_link(&mantou, &reset_link("https://example.com/", "abc"))
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "mantou https://example.com/reset#token=abc\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+// The layout of users.json. Files are `{"version": N, "users": {...}}`; the
+// bare map of users from before versioning is version 0. Older files are
+// brought up to date on load by running them through `MIGRATIONS` in order,
+// and the original is kept next to them.
+//
+// To change the layout: bump `USERS_FILE_VERSION` and add the step from the
+// previous version at the end of `MIGRATIONS`. Steps work on plain JSON, so
+// they keep working whatever `User` turns into later.
+use crate::encryption::{self, Key};
+use crate::{write_atomically, User, Username};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+pub const USERS_FILE_VERSION: u64 = 1;
+
+// `MIGRATIONS[n]` takes a file from version `n` to `n + 1`.
+const MIGRATIONS: [fn(Value) -> io::Result<Value>; USERS_FILE_VERSION as usize] = [wrap_v0];
+
+// Version 0 -> 1: put the bare map in the envelope.
+fn wrap_v0(users: Value) -> io::Result<Value> {
+    Ok(json!({ "version": 1, "users": users }))
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u64,
+    users: &'a HashMap<Username, User>,
+}
+
+// A bare map has users for values, never a number, so even a user called
+// `version` can't be mistaken for an envelope.
+fn version(file: &Value) -> u64 {
+    file.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+// Any version of the file, as the current one. Also returns the version it
+// was in, so callers know whether anything changed.
+pub fn migrate_users_file(mut file: Value) -> io::Result<(Value, u64)> {
+    let found = version(&file);
+    if found > USERS_FILE_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "version {found} is newer than this program understands ({USERS_FILE_VERSION})"
+            ),
+        ));
+    }
+    for step in &MIGRATIONS[found as usize..] {
+        file = step(file)?;
+    }
+    Ok((file, found))
+}
+
+// The users from any version of the file, with the version it was in.
+// Every user must be filed under their own username.
+pub fn decode_users(json: &str) -> io::Result<(HashMap<Username, User>, u64)> {
+    let (mut file, found) = migrate_users_file(serde_json::from_str(json)?)?;
+    let users: HashMap<String, User> = serde_json::from_value(file["users"].take())?;
+    let users = users
+        .into_iter()
+        .map(|(key, user)| match user.username == key.as_str() {
+            true => Ok((user.username.clone(), user)),
+            false => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{key:?} holds the user {:?}, run `userman doctor`",
+                    user.username.as_str()
+                ),
+            )),
+        })
+        .collect::<io::Result<_>>()?;
+    Ok((users, found))
+}
+
+pub fn encode_users(users: &HashMap<Username, User>) -> io::Result<String> {
+    let envelope = Envelope {
+        version: USERS_FILE_VERSION,
+        users,
+    };
+    Ok(serde_json::to_string_pretty(&envelope)?)
+}
+
+// Copy the file to `users.json.v0.bak`, or `users.json.v0.1.bak` and so on if
+// that is taken: an earlier backup is never overwritten.
+pub(crate) fn back_up(path: &Path, version: u64) -> io::Result<PathBuf> {
+    let mut attempt = 0;
+    loop {
+        let mut backup = path.as_os_str().to_owned();
+        match attempt {
+            0 => backup.push(format!(".v{version}.bak")),
+            _ => backup.push(format!(".v{version}.{attempt}.bak")),
+        }
+        let backup = PathBuf::from(backup);
+        if !backup.exists() {
+            std::fs::copy(path, &backup)?;
+            return Ok(backup);
+        }
+        attempt += 1;
+    }
+}
+
+// Read the users, upgrading an older file in place once its backup is made.
+pub(crate) fn load_and_upgrade(path: &Path) -> io::Result<HashMap<Username, User>> {
+    let (json, key) = encryption::read_plaintext(path)?;
+    let (users, found) = decode_users(&json)?;
+    if found < USERS_FILE_VERSION {
+        back_up(path, found)?;
+        write_users(path, &users, key.as_ref())?;
+    }
+    Ok(users)
+}
+
+pub(crate) fn write_users(
+    path: &Path,
+    users: &HashMap<Username, User>,
+    key: Option<&Key>,
+) -> io::Result<()> {
+    let json = Zeroizing::new(encode_users(users)?);
+    match key {
+        Some(key) => write_atomically(path, &encryption::encrypt(&json, key)?),
+        None => write_atomically(path, &json),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LoginAction, Password, Role};
+
+    #[test]
+    fn test_migrations() {
+        let path = std::env::temp_dir().join(format!("schema-{}.json", std::process::id()));
+        let adam = Username::new("adam").unwrap();
+        let v0 = r#"{"adam": {"username": "adam", "password": "", "action": {"Accept": "Admin"}}}"#;
+        std::fs::write(&path, v0).unwrap();
+
+        let users = load_and_upgrade(&path).unwrap();
+        assert_eq!(users[&adam].action, LoginAction::Accept(Role::Admin));
+        let backup = path.with_extension("json.v0.bak");
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), v0);
+        let upgraded: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(version(&upgraded), USERS_FILE_VERSION);
+
+        // Current files are left alone, and round trip.
+        let mut users = users;
+        users.insert(
+            Username::new("version").unwrap(),
+            User::new(
+                Username::new("version").unwrap(),
+                &Password::from("password"),
+                LoginAction::Accept(Role::User),
+            ),
+        );
+        let (decoded, found) = decode_users(&encode_users(&users).unwrap()).unwrap();
+        assert_eq!(found, USERS_FILE_VERSION);
+        assert_eq!(decoded.len(), 2);
+        // A v0 file with a user called `version` is still v0.
+        let bare = serde_json::to_value(&users).unwrap();
+        assert_eq!(version(&bare), 0);
+
+        let future = json!({ "version": USERS_FILE_VERSION + 1, "users": {} });
+        assert!(migrate_users_file(future).is_err());
+
+        std::fs::remove_file(&backup).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
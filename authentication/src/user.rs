@@ -1,19 +1,68 @@
-use crate::{hash_password, LoginAction};
+use crate::{hash_password, unix_seconds, LoginAction, Password, Role, Username};
 use serde::{Deserialize, Serialize}; // Refer to the top of the current crate's tree.
+use std::time::SystemTime;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct User {
-    pub username: String,
+    pub username: Username,
     pub(crate) password: String, // `pub (crate)` makes the field public for this crate only.
     pub action: LoginAction,
+    // Everything below came later: `default` lets files written before it load.
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    // Seconds since the Unix epoch. Unknown for users older than the field.
+    #[serde(default)]
+    pub created: Option<u64>,
+    // The last accepted login, see `manage::record_login`.
+    #[serde(default)]
+    pub last_login: Option<u64>,
+    // The role a denied user had, for `manage::activate` to give back.
+    #[serde(default)]
+    pub previous_role: Option<Role>,
 }
 
 impl User {
-    pub fn new(username: &str, password: &str, action: LoginAction) -> Self {
+    pub fn new(username: Username, password: &Password, action: LoginAction) -> Self {
         Self {
-            username: username.to_string(), // Convert a &str into a String.
-            password: hash_password(password),
+            created: Some(unix_seconds(SystemTime::now())),
+            ..Self::with_hash(username, &hash_password(password), action)
+        }
+    }
+
+    // For users whose password was hashed elsewhere, e.g. when importing.
+    // Nothing is known about them beyond that.
+    pub fn with_hash(username: Username, password_hash: &str, action: LoginAction) -> Self {
+        Self {
+            username,
+            password: password_hash.to_string(),
             action,
+            display_name: None,
+            email: None,
+            created: None,
+            last_login: None,
+            previous_role: None,
         }
     }
+
+    pub fn password_hash(&self) -> &str {
+        &self.password
+    }
+}
+
+// Hand-written so the password hash never ends up in a log.
+impl std::fmt::Debug for User {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("User")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("action", &self.action)
+            .field("display_name", &self.display_name)
+            .field("email", &self.email)
+            .field("created", &self.created)
+            .field("last_login", &self.last_login)
+            .field("previous_role", &self.previous_role)
+            .finish()
+    }
 }
@@ -0,0 +1,95 @@
+use crate::UserError;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+pub const MAX_USERNAME_LENGTH: usize = 32;
+
+// A username in the one form it is stored and looked up by: trimmed, NFKC
+// normalized and case folded, so "Alice", " alice " and "ＡＬＩＣＥ" are all the
+// same user. Only letters, digits and `.`, `_`, `-` are allowed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Username(String);
+
+impl Username {
+    pub fn new(username: &str) -> Result<Self, UserError> {
+        // Folding can produce characters that normalize further, hence NFKC twice.
+        let folded = caseless::default_case_fold_str(&username.trim().nfkc().collect::<String>());
+        let normalized: String = folded.nfkc().collect();
+
+        let length = normalized.chars().count();
+        let allowed = |c: char| c.is_alphanumeric() || matches!(c, '.' | '_' | '-');
+        if length == 0 || length > MAX_USERNAME_LENGTH || !normalized.chars().all(allowed) {
+            return Err(UserError::InvalidUsername(username.to_string()));
+        }
+        Ok(Self(normalized))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for Username {
+    type Err = UserError;
+
+    fn from_str(username: &str) -> Result<Self, Self::Err> {
+        Self::new(username)
+    }
+}
+
+impl TryFrom<String> for Username {
+    type Error = UserError;
+
+    fn try_from(username: String) -> Result<Self, Self::Error> {
+        Self::new(&username)
+    }
+}
+
+impl From<Username> for String {
+    fn from(username: Username) -> Self {
+        username.0
+    }
+}
+
+impl AsRef<str> for Username {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<&str> for Username {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl std::fmt::Display for Username {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_username() {
+        for same in ["alice", " Alice ", "ALICE", "ＡＬＩＣＥ"] {
+            assert_eq!(Username::new(same).unwrap(), "alice");
+        }
+        assert_eq!(
+            Username::new("Straße").unwrap(),
+            Username::new("STRASSE").unwrap()
+        );
+        assert_eq!(Username::new("dept.1_a-b").unwrap(), "dept.1_a-b");
+        for invalid in ["", "   ", "ba ga", "a/b", "adam\n2", &"a".repeat(33)] {
+            assert!(Username::new(invalid).is_err(), "{invalid:?} was accepted");
+        }
+
+        let username: Username = serde_json::from_str("\" Mantou\"").unwrap();
+        assert_eq!(serde_json::to_string(&username).unwrap(), "\"mantou\"");
+        assert!(serde_json::from_str::<Username>("\"ba ga\"").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use authentication::manage::MIN_PASSWORD_LENGTH;
 use authentication::*;
 
 fn user_accepted(role: &Role) {
@@ -5,9 +6,14 @@ fn user_accepted(role: &Role) {
 }
 
 fn main() {
-    let test = User::new("test", "test", LoginAction::Accept(Role::Admin));
+    let _test = User::new(
+        Username::new("test").unwrap(),
+        &Password::from("test"),
+        LoginAction::Accept(Role::Admin),
+    );
     // build_users_file();
     let users = get_users();
+    let groups = get_groups();
 
     // Using vectors:
     /*// `push` is one way to add an element to vectors.
@@ -32,15 +38,85 @@ fn main() {
 
     println!("Enter your password:");
     stdin.read_line(&mut password).unwrap();
+    let password = Password::from(password);
 
-    match login(&users, &username, &password) {
-        None => {
-            println!("{} is not a known user.", username.trim());
-            println!("This is where we handle new users.");
+    match login_with_groups(&users, &groups, &username, &password) {
+        None => match Username::new(&username) {
+            Ok(known) if users.contains_key(&known) => println!("Wrong password."),
+            _ => {
+                println!("{} is not a known user.", username.trim());
+                register(username.trim());
+            }
+        },
+        Some(login_action) => {
+            if let (LoginAction::Accept(_), Ok(username)) =
+                (&login_action, Username::new(&username))
+            {
+                // Only the time is saved, to the file as it is now. The login
+                // stands even if it can't be.
+                let _ = update_users_file("users.json", |users| {
+                    manage::record_login(users, &username, std::time::SystemTime::now())
+                });
+            }
+            login_action.do_login(user_accepted, |reason| {
+                println!("Access denied!");
+                println!("{reason:?}");
+            })
         }
-        Some(login_action) => login_action.do_login(user_accepted, |reason| {
-            println!("Access denied!");
-            println!("{reason:?}");
-        }),
     }
 }
+
+// Offer an unknown user an account. It can't be used until an admin runs
+// `userman approve`. Registered in the file as it is once the prompts are done,
+// so nothing saved meanwhile is undone, and the name is checked against it.
+fn register(username: &str) {
+    let stdin = std::io::stdin();
+    println!("Would you like to register? [y/N]");
+    let mut answer = String::new();
+    stdin.read_line(&mut answer).unwrap();
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        return;
+    }
+    let username = match Username::new(username) {
+        Ok(username) => username,
+        Err(e) => return println!("Sorry, {e}."),
+    };
+
+    // Ask until the password is acceptable and typed the same twice; an empty line gives up.
+    loop {
+        println!("Choose a password (at least {MIN_PASSWORD_LENGTH} characters):");
+        let password = read_password(&stdin);
+        if password.is_empty() {
+            return;
+        }
+        if let Err(e) = manage::validate_password(&password) {
+            println!("Sorry, {e}.");
+            continue;
+        }
+        println!("Enter it again:");
+        if read_password(&stdin) != password {
+            println!("The passwords don't match.");
+            continue;
+        }
+
+        match update_users_file("users.json", |users| {
+            manage::register(users, &username, &password)
+        }) {
+            Ok(Ok(())) => {
+                println!("Thanks {username}, an admin has to approve you before you can log in.");
+            }
+            Ok(Err(e)) => println!("Sorry, {e}."),
+            Err(e) => println!("Sorry, unable to save users.json: {e}."),
+        }
+        return;
+    }
+}
+
+// Trimmed in place, as `login` ignores surrounding whitespace too.
+fn read_password(stdin: &std::io::Stdin) -> Password {
+    let mut password = String::new();
+    stdin.read_line(&mut password).unwrap();
+    password.truncate(password.trim_end().len());
+    password.drain(..password.len() - password.trim_start().len());
+    Password::from(password)
+}
@@ -0,0 +1,14 @@
+// Whatever arrives on the socket, the server's decoder must fail cleanly
+// rather than panic or allocate past `MAX_MESSAGE_SIZE`, and anything it
+// accepts must survive being encoded again.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use login_protocol::{decode_request, encode_request};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(request) = decode_request(data) {
+        let bytes = encode_request(&request).expect("a decoded request encodes");
+        assert_eq!(decode_request(&bytes).unwrap(), request);
+    }
+});
@@ -0,0 +1,255 @@
+// Remote user administration, as used by `userman --remote`. An admin
+// connection opens with `ADMIN_MAGIC`, then carries length-prefixed bincode
+// frames: an `AdminRequest` from the client, an `AdminResponse` back. The first
+// request must authenticate an admin, and the session lasts as long as the
+// connection.
+use authentication::{LoginAction, Password, Role, Username};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+// Read as the length of a `LoginRequest` username this is far beyond
+// `MAX_MESSAGE_SIZE`, so no login request can ever be mistaken for it.
+pub const ADMIN_MAGIC: [u8; 8] = *b"\xffADMIN\x00\x01";
+
+// A user list has to fit in one frame.
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AdminRequest {
+    Authenticate {
+        username: String,
+        password: Password,
+    },
+    ListUsers,
+    AddUser {
+        username: String,
+        password: Password,
+        role: Role,
+    },
+    DeleteUser {
+        username: String,
+    },
+    ChangePassword {
+        username: String,
+        password: Password,
+    },
+}
+
+// What a remote admin sees of a user: never the password hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserSummary {
+    pub username: Username,
+    pub action: LoginAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AdminResponse {
+    Done,
+    Users(Vec<UserSummary>),
+    Error(String),
+}
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(MAX_FRAME_SIZE as u64)
+}
+
+pub async fn write_frame<T: Serialize>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    message: &T,
+) -> anyhow::Result<()> {
+    let bytes = options().serialize(message)?;
+    writer.write_u32(bytes.len() as u32).await?;
+    writer.write_all(&bytes).await?;
+    Ok(())
+}
+
+// `None` when the other side hung up between frames.
+pub async fn read_frame<T: DeserializeOwned>(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> anyhow::Result<Option<T>> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_FRAME_SIZE {
+        anyhow::bail!("a {len} byte frame is too large");
+    }
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes).await?;
+    Ok(Some(options().deserialize(&bytes)?))
+}
+
+// The server side of an admin connection. `received` holds whatever was read
+// along with the magic; `handle` answers each request in turn. It is async so
+// that saving and hashing can be handed off the runtime.
+pub async fn serve_admin<F: Future<Output = AdminResponse>>(
+    mut socket: TcpStream,
+    received: &[u8],
+    mut handle: impl FnMut(AdminRequest) -> F,
+) -> anyhow::Result<()> {
+    let (reader, mut writer) = socket.split();
+    let mut reader = received.chain(reader);
+    while let Some(request) = read_frame(&mut reader).await? {
+        write_frame(&mut writer, &handle(request).await).await?;
+    }
+    Ok(())
+}
+
+pub struct AdminClient {
+    stream: TcpStream,
+    timeout: Duration,
+}
+
+impl AdminClient {
+    // Connect and authenticate; fails unless `username` is an admin.
+    pub async fn connect(
+        address: &str,
+        request_timeout: Duration,
+        username: &str,
+        password: &Password,
+    ) -> anyhow::Result<Self> {
+        let mut stream = timeout(request_timeout, TcpStream::connect(address)).await??;
+        stream.write_all(&ADMIN_MAGIC).await?;
+        let mut client = Self {
+            stream,
+            timeout: request_timeout,
+        };
+        client
+            .request(AdminRequest::Authenticate {
+                username: username.to_string(),
+                password: password.clone(),
+            })
+            .await?;
+        Ok(client)
+    }
+
+    pub async fn list_users(&mut self) -> anyhow::Result<Vec<UserSummary>> {
+        match self.request(AdminRequest::ListUsers).await? {
+            AdminResponse::Users(users) => Ok(users),
+            response => anyhow::bail!("unexpected response {response:?}"),
+        }
+    }
+
+    pub async fn add_user(
+        &mut self,
+        username: &str,
+        password: &Password,
+        role: Role,
+    ) -> anyhow::Result<()> {
+        self.request(AdminRequest::AddUser {
+            username: username.to_string(),
+            password: password.clone(),
+            role,
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_user(&mut self, username: &str) -> anyhow::Result<()> {
+        self.request(AdminRequest::DeleteUser {
+            username: username.to_string(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn change_password(
+        &mut self,
+        username: &str,
+        password: &Password,
+    ) -> anyhow::Result<()> {
+        self.request(AdminRequest::ChangePassword {
+            username: username.to_string(),
+            password: password.clone(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    // An `Error` from the server becomes an `Err`.
+    async fn request(&mut self, request: AdminRequest) -> anyhow::Result<AdminResponse> {
+        timeout(self.timeout, self.exchange(request)).await?
+    }
+
+    async fn exchange(&mut self, request: AdminRequest) -> anyhow::Result<AdminResponse> {
+        write_frame(&mut self.stream, &request).await?;
+        match read_frame(&mut self.stream).await? {
+            Some(AdminResponse::Error(e)) => anyhow::bail!(e),
+            Some(response) => Ok(response),
+            None => anyhow::bail!("the login server closed the connection"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // Lets in "adam" / "password" only, and knows one user: adam.
+    async fn fake_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut magic = [0; 8];
+                    socket.read_exact(&mut magic).await.unwrap();
+                    assert_eq!(magic, ADMIN_MAGIC);
+                    let mut admin = false;
+                    serve_admin(socket, &[], |request| {
+                        std::future::ready(match request {
+                            AdminRequest::Authenticate { username, password } => {
+                                admin = username == "adam" && password.expose() == "password";
+                                match admin {
+                                    true => AdminResponse::Done,
+                                    false => AdminResponse::Error("not an admin".to_string()),
+                                }
+                            }
+                            _ if !admin => AdminResponse::Error("authenticate first".to_string()),
+                            AdminRequest::ListUsers => AdminResponse::Users(vec![UserSummary {
+                                username: Username::new("adam").unwrap(),
+                                action: LoginAction::Accept(Role::Admin),
+                            }]),
+                            _ => AdminResponse::Done,
+                        })
+                    })
+                    .await
+                    .unwrap();
+                });
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn test_admin_session() {
+        // The magic can never pass for a login request.
+        assert!(crate::decode_request(&ADMIN_MAGIC).is_err());
+
+        let address = fake_server().await;
+        let wait = Duration::from_secs(1);
+        assert!(
+            AdminClient::connect(&address, wait, "adam", &Password::from("wrong"))
+                .await
+                .is_err()
+        );
+
+        let mut client = AdminClient::connect(&address, wait, "adam", &Password::from("password"))
+            .await
+            .unwrap();
+        let users = client.list_users().await.unwrap();
+        assert_eq!(users[0].action, LoginAction::Accept(Role::Admin));
+        client.delete_user("mantou").await.unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{decode_response, encode_request, LoginRequest, MAX_MESSAGE_SIZE};
+use authentication::{LoginAction, Password};
+use parking_lot::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+// One connection to the login server, re-used for as many requests as we like.
+pub struct LoginClient {
+    stream: TcpStream,
+    timeout: Duration,
+}
+
+impl LoginClient {
+    pub async fn connect(address: &str, request_timeout: Duration) -> anyhow::Result<Self> {
+        let stream = timeout(request_timeout, TcpStream::connect(address)).await??;
+        Ok(Self {
+            stream,
+            timeout: request_timeout,
+        })
+    }
+
+    // `None` means the server doesn't know the user (or the password was wrong).
+    pub async fn login(
+        &mut self,
+        username: &str,
+        password: &Password,
+    ) -> anyhow::Result<Option<LoginAction>> {
+        let request = LoginRequest {
+            username: username.to_string(),
+            password: password.clone(),
+        };
+        let message = encode_request(&request)?;
+        timeout(self.timeout, self.exchange(&message)).await?
+    }
+
+    async fn exchange(&mut self, message: &[u8]) -> anyhow::Result<Option<LoginAction>> {
+        self.stream.write_all(message).await?;
+
+        let mut buf = vec![0; MAX_MESSAGE_SIZE];
+        let n = self.stream.read(&mut buf).await?;
+        if n == 0 {
+            anyhow::bail!("the login server closed the connection");
+        }
+        Ok(decode_response(&buf[0..n])?)
+    }
+}
+
+// A handful of idle connections kept open between requests, so a busy web
+// server doesn't pay for a new TCP handshake on every login.
+pub struct LoginPool {
+    address: String,
+    timeout: Duration,
+    max_idle: usize,
+    idle: Mutex<Vec<LoginClient>>,
+}
+
+impl LoginPool {
+    pub fn new(address: &str, timeout: Duration, max_idle: usize) -> Self {
+        Self {
+            address: address.to_string(),
+            timeout,
+            max_idle,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &Password,
+    ) -> anyhow::Result<Option<LoginAction>> {
+        // An idle connection may have been closed by a server restart since we
+        // last used it: in that case, retry once on a fresh one.
+        let pooled = self.idle.lock().pop();
+        if let Some(mut client) = pooled {
+            if let Ok(response) = client.login(username, password).await {
+                self.release(client);
+                return Ok(response);
+            }
+        }
+
+        let mut client = LoginClient::connect(&self.address, self.timeout).await?;
+        let response = client.login(username, password).await?;
+        self.release(client);
+        Ok(response)
+    }
+
+    fn release(&self, client: LoginClient) {
+        let mut idle = self.idle.lock();
+        if idle.len() < self.max_idle {
+            idle.push(client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decode_request, encode_response};
+    use authentication::Role;
+    use tokio::net::TcpListener;
+
+    // Accepts every login on up to `connections` connections, then stops.
+    async fn fake_server(connections: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            for _ in 0..connections {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = vec![0; MAX_MESSAGE_SIZE];
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        if n == 0 {
+                            return;
+                        }
+                        let request = decode_request(&buf[0..n]).unwrap();
+                        let response = Some(LoginAction::Accept(if request.username == "adam" {
+                            Role::Admin
+                        } else {
+                            Role::User
+                        }));
+                        let bytes = encode_response(&response).unwrap();
+                        socket.write_all(&bytes).await.unwrap();
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn test_pool_reuses_connections() {
+        // Only one connection is ever accepted, so every login must share it.
+        let pool = LoginPool::new(&fake_server(1).await, Duration::from_secs(1), 4);
+        for _ in 0..3 {
+            assert_eq!(
+                pool.login("adam", &Password::from("password"))
+                    .await
+                    .unwrap(),
+                Some(LoginAction::Accept(Role::Admin))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        // Accept the connection but never answer.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let pool = LoginPool::new(&address, Duration::from_millis(100), 4);
+        assert!(pool
+            .login("adam", &Password::from("password"))
+            .await
+            .is_err());
+    }
+}
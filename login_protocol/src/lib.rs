@@ -0,0 +1,79 @@
+// The wire protocol spoken by the tcp_login_server: the client sends a bincode
+// `LoginRequest`, the server answers with a bincode `Option<LoginAction>`.
+// Admin connections speak their own protocol, see `admin`.
+use authentication::{LoginAction, Password};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+pub mod admin;
+mod client;
+pub use admin::AdminClient;
+pub use client::{LoginClient, LoginPool};
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8123";
+
+// Both requests and responses fit in a single read of this size.
+pub const MAX_MESSAGE_SIZE: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: Password,
+}
+
+// The same encoding as `bincode::serialize`, but refusing to allocate more
+// than a message could ever hold when the input is garbage.
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(MAX_MESSAGE_SIZE as u64)
+}
+
+pub fn encode_request(request: &LoginRequest) -> bincode::Result<Vec<u8>> {
+    options().serialize(request)
+}
+
+pub fn decode_request(bytes: &[u8]) -> bincode::Result<LoginRequest> {
+    options().deserialize(bytes)
+}
+
+pub fn encode_response(response: &Option<LoginAction>) -> bincode::Result<Vec<u8>> {
+    options().serialize(response)
+}
+
+pub fn decode_response(bytes: &[u8]) -> bincode::Result<Option<LoginAction>> {
+    options().deserialize(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use authentication::Role;
+
+    #[test]
+    fn test_round_trip() {
+        let request = LoginRequest {
+            username: "adam".to_string(),
+            password: Password::from("password"),
+        };
+        let bytes = encode_request(&request).unwrap();
+        // Still readable by plain `bincode::deserialize`, as older clients use.
+        assert_eq!(
+            bincode::deserialize::<LoginRequest>(&bytes).unwrap(),
+            request
+        );
+        assert_eq!(decode_request(&bytes).unwrap(), request);
+
+        let response = Some(LoginAction::Accept(Role::Admin));
+        let bytes = encode_response(&response).unwrap();
+        assert_eq!(decode_response(&bytes).unwrap(), response);
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        assert!(decode_request(&[]).is_err());
+        // A username claiming to be u64::MAX bytes long.
+        assert!(decode_request(&[0xff; 16]).is_err());
+    }
+}
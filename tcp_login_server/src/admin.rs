@@ -0,0 +1,213 @@
+// Answers `userman --remote`. Each connection is its own session: nothing but
+// `Authenticate` is served until an admin has logged in on it.
+use authentication::{
+    effective_action, login_with_groups, manage, Groups, LoginAction, Role, User, UserError,
+    Username,
+};
+use login_protocol::admin::{AdminRequest, AdminResponse, UserSummary};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+type UserMap = HashMap<Username, User>;
+
+pub struct Session {
+    path: PathBuf,
+    groups_path: PathBuf,
+    admin: Option<Username>,
+}
+
+impl Session {
+    // `path` is where changes are saved, `groups_path` where deleted users
+    // are taken out of their groups.
+    pub fn new(path: impl Into<PathBuf>, groups_path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            groups_path: groups_path.into(),
+            admin: None,
+        }
+    }
+
+    pub fn handle(
+        &mut self,
+        users: &RwLock<UserMap>,
+        groups: &RwLock<Groups>,
+        request: AdminRequest,
+    ) -> AdminResponse {
+        match request {
+            AdminRequest::Authenticate { username, password } => {
+                let action = login_with_groups(&users.read(), &groups.read(), &username, &password);
+                self.admin = match action {
+                    Some(LoginAction::Accept(Role::Admin)) => Username::new(&username).ok(),
+                    _ => None,
+                };
+                match self.admin {
+                    Some(_) => AdminResponse::Done,
+                    None => AdminResponse::Error("not an admin".to_string()),
+                }
+            }
+            _ if !self.is_admin(&users.read(), &groups.read()) => {
+                AdminResponse::Error("authenticate as an admin first".to_string())
+            }
+            AdminRequest::ListUsers => {
+                let mut summaries: Vec<UserSummary> = users
+                    .read()
+                    .values()
+                    .map(|user| UserSummary {
+                        username: user.username.clone(),
+                        action: user.action.clone(),
+                    })
+                    .collect();
+                summaries.sort_by(|a, b| a.username.cmp(&b.username));
+                AdminResponse::Users(summaries)
+            }
+            AdminRequest::AddUser {
+                username,
+                password,
+                role,
+            } => update(users, &self.path, |users| {
+                manage::add_user(users, &Username::new(&username)?, &password, role)
+            }),
+            AdminRequest::DeleteUser { username } => {
+                let response = update(users, &self.path, |users| {
+                    manage::delete_user(users, &Username::new(&username)?).map(|_| ())
+                });
+                if response != AdminResponse::Done {
+                    return response;
+                }
+                // So nobody given the username later inherits their groups.
+                match authentication::drop_deleted_members_from(&self.groups_path, &users.read()) {
+                    Ok(()) => response,
+                    Err(e) => {
+                        println!("Unable to save {}: {e}", self.groups_path.display());
+                        AdminResponse::Error("deleted, but unable to save groups".to_string())
+                    }
+                }
+            }
+            AdminRequest::ChangePassword { username, password } => {
+                update(users, &self.path, |users| {
+                    manage::change_password(users, &Username::new(&username)?, &password)
+                })
+            }
+        }
+    }
+
+    // Checked on every request: an admin who is deleted, locked or demoted
+    // (or leaves the group that made them one) mid-session loses it.
+    fn is_admin(&self, users: &UserMap, groups: &Groups) -> bool {
+        let admin = match &self.admin {
+            Some(admin) => admin,
+            None => return false,
+        };
+        users
+            .get(admin)
+            .map(|user| effective_action(groups, admin, user.action.clone()))
+            == Some(LoginAction::Accept(Role::Admin))
+    }
+}
+
+// Apply `change` to the file as it is now, not to our copy, which may be
+// older, and only take the result once it has been saved.
+fn update(
+    users: &RwLock<UserMap>,
+    path: &Path,
+    change: impl FnOnce(&mut UserMap) -> Result<(), UserError>,
+) -> AdminResponse {
+    let mut users = users.write();
+    let updated = authentication::update_users_file(path, |updated| {
+        change(updated)?;
+        Ok(updated.clone())
+    });
+    match updated {
+        Ok(Ok(updated)) => {
+            *users = updated;
+            AdminResponse::Done
+        }
+        Ok(Err(e)) => AdminResponse::Error(e.to_string()),
+        Err(e) => {
+            println!("Unable to save {}: {e}", path.display());
+            AdminResponse::Error("unable to save users".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use authentication::Password;
+
+    #[test]
+    fn test_admin_session() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("admin-session-{}.json", std::process::id()));
+        let groups_path = dir.join(format!("admin-session-groups-{}.json", std::process::id()));
+        let password = Password::from("password");
+        let [adam, mantou, baga, ops, interns] =
+            ["adam", "mantou", "baga", "ops", "interns"].map(|name| Username::new(name).unwrap());
+        let mut users = HashMap::new();
+        manage::add_user(&mut users, &adam, &password, Role::Admin).unwrap();
+        manage::add_user(&mut users, &mantou, &password, Role::User).unwrap();
+        manage::add_user(&mut users, &baga, &password, Role::User).unwrap();
+        let mut groups = Groups::new();
+        manage::create_group(&mut groups, &ops, Role::Admin).unwrap();
+        manage::create_group(&mut groups, &interns, Role::Limited).unwrap();
+        manage::add_member(&mut groups, &users, &ops, &baga).unwrap();
+        manage::add_member(&mut groups, &users, &interns, &mantou).unwrap();
+        authentication::save_users(&path, &users).unwrap();
+        authentication::save_groups(&groups_path, &groups).unwrap();
+        let (users, groups) = (RwLock::new(users), RwLock::new(groups));
+        let delete = || AdminRequest::DeleteUser {
+            username: "mantou".to_string(),
+        };
+        let authenticate = |username: &str| AdminRequest::Authenticate {
+            username: username.to_string(),
+            password: password.clone(),
+        };
+
+        let mut session = Session::new(&path, &groups_path);
+        assert!(matches!(
+            session.handle(&users, &groups, delete()),
+            AdminResponse::Error(_)
+        ));
+        assert!(matches!(
+            session.handle(&users, &groups, authenticate("mantou")),
+            AdminResponse::Error(_)
+        ));
+        assert_eq!(
+            session.handle(&users, &groups, authenticate(" Adam ")),
+            AdminResponse::Done
+        );
+        assert_eq!(
+            session.handle(&users, &groups, delete()),
+            AdminResponse::Done
+        );
+        assert!(!users.read().contains_key(&mantou));
+        assert!(authentication::load_users(&path)
+            .unwrap()
+            .contains_key(&adam));
+        // Out of their groups too.
+        let saved = authentication::load_groups(&groups_path).unwrap();
+        assert!(saved[&interns].members.is_empty());
+
+        // Locked out mid-session.
+        manage::lock(&mut users.write(), &adam, "Contact HR!").unwrap();
+        assert!(matches!(
+            session.handle(&users, &groups, AdminRequest::ListUsers),
+            AdminResponse::Error(_)
+        ));
+
+        // An admin through a group, until they leave it.
+        let mut session = Session::new(&path, &groups_path);
+        assert_eq!(
+            session.handle(&users, &groups, authenticate("baga")),
+            AdminResponse::Done
+        );
+        manage::remove_member(&mut groups.write(), &ops, &baga).unwrap();
+        assert!(matches!(
+            session.handle(&users, &groups, AdminRequest::ListUsers),
+            AdminResponse::Error(_)
+        ));
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(groups_path).unwrap();
+    }
+}
@@ -1,29 +1,31 @@
-use authentication::serde::{Deserialize, Serialize};
+use authentication::asynchronous::{blocking, Authenticator, FileStore, Hasher};
 use authentication::*;
-use once_cell::sync::Lazy;
-use parking_lot::RwLock;
-use std::collections::HashMap;
+use login_protocol::admin::{serve_admin, ADMIN_MAGIC};
+use login_protocol::{LoginClient, DEFAULT_ADDRESS, MAX_MESSAGE_SIZE};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
+    net::TcpListener,
     spawn,
 };
 
-static USERS: Lazy<RwLock<HashMap<String, User>>> = Lazy::new(|| RwLock::new(get_users()));
+mod admin;
 
-#[derive(Serialize, Deserialize)]
-struct LoginRequest {
-    username: String,
-    password: String,
-}
+// How many password hashes may be queued on the blocking pool at once.
+const HASH_QUEUE: usize = 64;
 
 async fn rpc_server() -> anyhow::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:8123").await?;
+    let store = FileStore::new("users.json", "groups.json");
+    let auth = Arc::new(Authenticator::open(store, Hasher::new(HASH_QUEUE)).await?);
+    let listener = TcpListener::bind(DEFAULT_ADDRESS).await?;
 
     loop {
-        let (mut socket, address) = listener.accept().await?;
+        let (mut socket, _address) = listener.accept().await?;
+        let auth = auth.clone();
         spawn(async move {
-            let mut buf = vec![0; 1024];
+            let mut buf = vec![0; MAX_MESSAGE_SIZE];
             loop {
                 let n = socket
                     .read(&mut buf)
@@ -34,12 +36,40 @@ async fn rpc_server() -> anyhow::Result<()> {
                     return;
                 }
 
+                // `userman --remote` switches the connection over to admin requests.
+                if let Some(received) = buf[0..n].strip_prefix(&ADMIN_MAGIC) {
+                    let session =
+                        Arc::new(Mutex::new(admin::Session::new("users.json", "groups.json")));
+                    let result = serve_admin(socket, received, |request| {
+                        let (auth, session) = (auth.clone(), session.clone());
+                        async move {
+                            if let Err(e) = auth.refresh().await {
+                                println!("Unable to reload users: {e}");
+                            }
+                            // Saving and hashing block, like they do for logins.
+                            blocking(move || {
+                                session.lock().handle(auth.users(), auth.groups(), request)
+                            })
+                            .await
+                        }
+                    })
+                    .await;
+                    if let Err(e) = result {
+                        println!("Admin session failed: {e}");
+                    }
+                    return;
+                }
+
                 let mut response = None;
-                if let Ok(request) = bincode::deserialize::<LoginRequest>(&buf[0..n]) {
-                    response = login(&USERS.read(), &request.username, &request.password);
+                if let Ok(request) = login_protocol::decode_request(&buf[0..n]) {
+                    // `userman` and the web admin change the files behind our back.
+                    if let Err(e) = auth.refresh().await {
+                        println!("Unable to reload users: {e}");
+                    }
+                    response = auth.login(&request.username, request.password).await;
                 }
 
-                let bytes = bincode::serialize(&response).unwrap();
+                let bytes = login_protocol::encode_response(&response).unwrap();
                 socket
                     .write_all(&bytes)
                     .await
@@ -47,24 +77,26 @@ async fn rpc_server() -> anyhow::Result<()> {
             }
         });
     }
-    Ok(())
 }
 
 async fn rpc_client() -> anyhow::Result<()> {
     let mut handles = Vec::new();
     for _ in 0..1000 {
         handles.push(tokio::spawn(async {
-            let mut client = LoginClient::new().await;
+            let mut client = LoginClient::connect(DEFAULT_ADDRESS, Duration::from_secs(5))
+                .await
+                .unwrap();
+            let password = Password::from("password");
             for _ in 0..10 {
                 let now = std::time::Instant::now();
-                let _result = client.login("adam", "password").await.unwrap();
+                let _result = client.login("adam", &password).await.unwrap();
                 let duration = now.elapsed();
                 println!("Login session took: {} usecs", duration.as_micros());
             }
         }));
     }
     for handle in handles {
-        handle.await;
+        handle.await?;
     }
 
     Ok(())
@@ -106,37 +138,6 @@ async fn rpc_client() -> anyhow::Result<()> {
     Ok(())*/
 }
 
-// Re-use our tcp connection.
-struct LoginClient(TcpStream);
-
-impl LoginClient {
-    async fn new() -> Self {
-        let stream = TcpStream::connect("127.0.0.1:8123").await.unwrap();
-        Self(stream)
-    }
-
-    async fn login(&mut self, username: &str, password: &str) -> anyhow::Result<LoginAction> {
-        let login_attempt = LoginRequest {
-            username: username.to_string(),
-            password: password.to_string(),
-        };
-        let message = bincode::serialize(&login_attempt)?;
-        self.0.write_all(&message).await?;
-
-        let mut buf = vec![0; 1024];
-        let n = self.0.read(&mut buf).await?;
-        let response: Option<LoginAction> = bincode::deserialize(&buf[0..n])?;
-
-        match response {
-            None => Err(anyhow::Error::msg("Unknown User")),
-            Some(login_action) => Ok(login_action),
-            _ => Ok(LoginAction::Denied(DeniedReason::AccountLocked {
-                reason: "Unknown User".to_string(),
-            })),
-        }
-    }
-}
-
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args: Vec<String> = std::env::args().collect();
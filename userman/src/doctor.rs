@@ -0,0 +1,265 @@
+// `doctor`: find the damage hand edits do to the users file, and repair what
+// can be repaired without guessing. It reads the file as plain JSON, since a
+// damaged file is exactly one that `load_users` refuses.
+use crate::UserMap;
+use authentication::serde::Deserialize;
+use authentication::{
+    encryption, is_password_hash, migrate_users_file, LoginAction, Role, User, UserError, Username,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+// A user as written in the file, before any checks.
+#[derive(Deserialize)]
+#[serde(crate = "authentication::serde")]
+struct RawUser {
+    username: String,
+    password: String,
+    action: LoginAction,
+    // Carried over as they are.
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    created: Option<u64>,
+    #[serde(default)]
+    last_login: Option<u64>,
+    #[serde(default)]
+    previous_role: Option<Role>,
+}
+
+type RawUsers = HashMap<String, RawUser>;
+
+#[derive(Debug, PartialEq)]
+enum Problem {
+    // The map key isn't the user's own username.
+    KeyMismatch {
+        key: String,
+        username: String,
+    },
+    // Not in the normalized form `login` looks users up by, so they can never log in.
+    NotNormalized {
+        username: String,
+        normalized: String,
+    },
+    // Several entries end up with the same username once normalized.
+    Duplicate {
+        username: String,
+        keys: Vec<String>,
+    },
+    InvalidUsername {
+        key: String,
+    },
+    // Lowercase hex: the right hash, written the wrong way.
+    LowercaseHash {
+        key: String,
+    },
+    BadHash {
+        key: String,
+    },
+}
+
+impl Problem {
+    fn fixable(&self) -> bool {
+        !matches!(
+            self,
+            Self::Duplicate { .. } | Self::InvalidUsername { .. } | Self::BadHash { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::KeyMismatch { key, username } => {
+                write!(f, "{key:?} holds the user {username:?}")
+            }
+            Self::NotNormalized {
+                username,
+                normalized,
+            } => write!(f, "{username:?} can't log in, it should be {normalized:?}"),
+            Self::Duplicate { username, keys } => {
+                write!(f, "{keys:?} are all the same user {username:?}")
+            }
+            Self::InvalidUsername { key } => write!(f, "{key:?} is not a valid username"),
+            Self::LowercaseHash { key } => write!(f, "{key:?} has a lowercase password hash"),
+            Self::BadHash { key } => write!(f, "{key:?} has a malformed password hash"),
+        }
+    }
+}
+
+fn check(users: &RawUsers) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut by_username: BTreeMap<Username, Vec<String>> = BTreeMap::new();
+    let mut keys: Vec<&String> = users.keys().collect();
+    keys.sort();
+
+    for key in keys {
+        let user = &users[key];
+        if *key != user.username {
+            problems.push(Problem::KeyMismatch {
+                key: key.clone(),
+                username: user.username.clone(),
+            });
+        }
+        match Username::new(&user.username) {
+            Ok(normalized) => {
+                if normalized != user.username.as_str() {
+                    problems.push(Problem::NotNormalized {
+                        username: user.username.clone(),
+                        normalized: normalized.to_string(),
+                    });
+                }
+                by_username.entry(normalized).or_default().push(key.clone());
+            }
+            Err(_) => problems.push(Problem::InvalidUsername { key: key.clone() }),
+        }
+        if !is_password_hash(&user.password) {
+            if is_password_hash(&user.password.to_uppercase()) {
+                problems.push(Problem::LowercaseHash { key: key.clone() });
+            } else {
+                problems.push(Problem::BadHash { key: key.clone() });
+            }
+        }
+    }
+
+    for (username, keys) in by_username {
+        if keys.len() > 1 {
+            problems.push(Problem::Duplicate {
+                username: username.to_string(),
+                keys,
+            });
+        }
+    }
+    problems
+}
+
+// File every user under their normalized username, with an uppercase hash.
+// Only called once `check` found nothing that needs a person to decide.
+fn fix(users: &RawUsers) -> Result<UserMap, UserError> {
+    users
+        .values()
+        .map(|user| {
+            let username = Username::new(&user.username)?;
+            let hash = user.password.to_uppercase();
+            let mut fixed = User::with_hash(username.clone(), &hash, user.action.clone());
+            fixed.display_name = user.display_name.clone();
+            fixed.email = user.email.clone();
+            fixed.created = user.created;
+            fixed.last_login = user.last_login;
+            fixed.previous_role = user.previous_role.clone();
+            Ok((username, fixed))
+        })
+        .collect()
+}
+
+// Brought up to the current layout first, so older files are checked too.
+fn read(path: &Path) -> Result<RawUsers, String> {
+    let json = encryption::read_users_json(path).map_err(|e| e.to_string())?;
+    let file = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+    let (mut file, _) = migrate_users_file(file).map_err(|e| e.to_string())?;
+    serde_json::from_value(file["users"].take()).map_err(|e| e.to_string())
+}
+
+pub fn doctor(path: &Path, apply_fixes: bool) {
+    let users = read(path).unwrap_or_else(|e| {
+        println!("Unable to read {}: {e}, aborting", path.display());
+        std::process::exit(1);
+    });
+    let problems = check(&users);
+    if problems.is_empty() {
+        println!("No problems found");
+        return;
+    }
+    for problem in &problems {
+        let note = if problem.fixable() {
+            ""
+        } else {
+            " (fix by hand)"
+        };
+        println!("{problem}{note}");
+    }
+
+    if !apply_fixes {
+        println!("Run with --fix to repair what can be repaired");
+        std::process::exit(1);
+    }
+    if !problems.iter().all(Problem::fixable) {
+        println!("Fix the problems marked by hand first, nothing was changed");
+        std::process::exit(1);
+    }
+    let result = fix(&users)
+        .map_err(|e| e.to_string())
+        .and_then(|users| authentication::save_users(path, &users).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => println!("Fixed {} problem(s)", problems.len()),
+        Err(e) => {
+            println!("{e}, aborting");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use authentication::{hash_password, Password};
+
+    fn user(username: &str, hash: &str) -> RawUser {
+        RawUser {
+            username: username.to_string(),
+            password: hash.to_string(),
+            action: LoginAction::Accept(Role::User),
+            display_name: None,
+            email: None,
+            created: Some(1_679_131_845),
+            last_login: None,
+            previous_role: None,
+        }
+    }
+
+    #[test]
+    fn test_doctor() {
+        let password = Password::from("password");
+        let hash = hash_password(&password);
+        let mut users = HashMap::new();
+        users.insert("adam".to_string(), user("adam", &hash));
+        users.insert("Mike".to_string(), user("Mike", &hash.to_lowercase()));
+        users.insert("jake".to_string(), user("Jake ", &hash));
+        users.insert("KEVIN".to_string(), user("kevin", "nonsense"));
+        users.insert("kevin".to_string(), user("Kevin", &hash));
+
+        let problems = check(&users);
+        assert!(problems.contains(&Problem::NotNormalized {
+            username: "Mike".to_string(),
+            normalized: "mike".to_string()
+        }));
+        assert!(problems.contains(&Problem::LowercaseHash {
+            key: "Mike".to_string()
+        }));
+        assert!(problems.contains(&Problem::KeyMismatch {
+            key: "jake".to_string(),
+            username: "Jake ".to_string()
+        }));
+        assert!(problems.contains(&Problem::Duplicate {
+            username: "kevin".to_string(),
+            keys: vec!["KEVIN".to_string(), "kevin".to_string()]
+        }));
+
+        // With the kevins sorted out by hand, the rest can be fixed.
+        users.remove("KEVIN");
+        assert!(check(&users).iter().all(Problem::fixable));
+        let fixed = fix(&users).unwrap();
+        for username in ["mike", "jake", "kevin"] {
+            assert_eq!(
+                authentication::login(&fixed, username, &password),
+                Some(LoginAction::Accept(Role::User))
+            );
+            assert_eq!(
+                fixed[&Username::new(username).unwrap()].created,
+                Some(1_679_131_845)
+            );
+        }
+    }
+}
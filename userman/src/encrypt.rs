@@ -0,0 +1,73 @@
+// `encrypt`, `decrypt` and `rekey`: switch users.json between plain and
+// encrypted. Once it is encrypted, every other command needs the key in
+// `USERS_KEY_FILE` or `USERS_PASSPHRASE`.
+use authentication::encryption::{self, Key};
+use authentication::{manage, Password};
+use std::path::{Path, PathBuf};
+
+const USERS_FILE: &str = "users.json";
+
+pub fn encrypt(key_file: Option<PathBuf>) -> anyhow::Result<()> {
+    let key = match key_file {
+        Some(path) => {
+            if !path.exists() {
+                encryption::create_key_file(&path)?;
+                println!(
+                    "Created the key file {}: keep it safe, and apart from {USERS_FILE}",
+                    path.display()
+                );
+            }
+            Key::File(path)
+        }
+        None => new_passphrase("Passphrase: ")?,
+    };
+    encryption::encrypt_users_file(USERS_FILE, &key)?;
+    println!("{USERS_FILE} is encrypted");
+    if Path::new(&format!("{USERS_FILE}.v0.bak")).exists() {
+        println!("Backups of older versions of {USERS_FILE} are still in plain text");
+    }
+    Ok(())
+}
+
+pub fn decrypt(key_file: Option<PathBuf>) -> anyhow::Result<()> {
+    let key = current_key(key_file)?;
+    encryption::decrypt_users_file(USERS_FILE, &key)?;
+    println!("{USERS_FILE} is decrypted");
+    Ok(())
+}
+
+pub fn rekey(key_file: Option<PathBuf>, new_key_file: Option<PathBuf>) -> anyhow::Result<()> {
+    let old = current_key(key_file)?;
+    let new = match new_key_file {
+        Some(path) => {
+            encryption::create_key_file(&path)?;
+            println!("Created the key file {}", path.display());
+            Key::File(path)
+        }
+        None => new_passphrase("New passphrase: ")?,
+    };
+    encryption::rekey_users_file(USERS_FILE, &old, &new)?;
+    println!("{USERS_FILE} is encrypted with the new key");
+    Ok(())
+}
+
+fn current_key(key_file: Option<PathBuf>) -> anyhow::Result<Key> {
+    Ok(match key_file {
+        Some(path) => Key::File(path),
+        None => Key::Passphrase(Password::from(rpassword::prompt_password(
+            "Current passphrase: ",
+        )?)),
+    })
+}
+
+// Prompted twice, like passwords, and held to the same minimum length.
+fn new_passphrase(prompt: &str) -> anyhow::Result<Key> {
+    let passphrase = Password::from(rpassword::prompt_password(prompt)?);
+    manage::validate_password(&passphrase)?;
+    let confirmation = Password::from(rpassword::prompt_password("Confirm passphrase: ")?);
+    anyhow::ensure!(
+        passphrase.expose() == confirmation.expose(),
+        "the passphrases don't match"
+    );
+    Ok(Key::Passphrase(passphrase))
+}
@@ -0,0 +1,67 @@
+// `group`: give a role to many users at once. Groups live in groups.json,
+// next to users.json.
+use crate::{exit_on_error, RoleArg, UserMap};
+use authentication::{load_groups, manage, save_groups, Groups, Username};
+use clap::Subcommand;
+
+#[derive(Subcommand)]
+pub enum GroupCommands {
+    /// Create a group. Its members get its role if it's higher than their own.
+    Create {
+        /// Group name; the same rules as for usernames apply.
+        name: Username,
+        /// Role granted to the members.
+        #[arg(value_enum)]
+        role: RoleArg,
+    },
+    /// Add a user to a group.
+    AddMember {
+        /// Group name.
+        group: Username,
+        /// Username.
+        username: Username,
+    },
+    /// Take a user out of a group.
+    RemoveMember {
+        /// Group name.
+        group: Username,
+        /// Username.
+        username: Username,
+    },
+    /// List groups, with their role and members.
+    List,
+}
+
+pub fn run(command: GroupCommands, users: &UserMap) {
+    let mut groups = load_groups("groups.json").unwrap_or_else(|e| {
+        println!("Unable to read groups.json: {e}, aborting");
+        std::process::exit(1);
+    });
+    let result = match command {
+        GroupCommands::Create { name, role } => {
+            manage::create_group(&mut groups, &name, role.into())
+        }
+        GroupCommands::AddMember { group, username } => {
+            manage::add_member(&mut groups, users, &group, &username)
+        }
+        GroupCommands::RemoveMember { group, username } => {
+            manage::remove_member(&mut groups, &group, &username)
+        }
+        GroupCommands::List => return list_groups(&groups),
+    };
+    exit_on_error(result);
+    exit_on_error(save_groups("groups.json", &groups));
+}
+
+fn list_groups(groups: &Groups) {
+    let mut groups: Vec<_> = groups.values().collect();
+    groups.sort_by(|a, b| a.name.cmp(&b.name));
+
+    println!("{:<20}{:<10}Members", "Group", "Role");
+    println!("{:-<50}", "");
+    for group in groups {
+        let members: Vec<&str> = group.members.iter().map(Username::as_str).collect();
+        let role = format!("{:?}", group.role);
+        println!("{:<20}{:<10}{}", group.name, role, members.join(", "));
+    }
+}
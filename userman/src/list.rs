@@ -0,0 +1,191 @@
+// `list` and `show`: users as a colored table for people, or JSON / CSV for scripts.
+use crate::{RoleArg, UserMap};
+use authentication::serde::Serialize;
+use authentication::{DeniedReason, LoginAction, Role, User, Username};
+use clap::ValueEnum;
+use colored::Colorize;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+// Which users `list` shows. Every filter given must match.
+#[derive(clap::Args)]
+pub struct Filter {
+    /// Only active users with this role.
+    #[arg(long, value_enum)]
+    role: Option<RoleArg>,
+    /// Only locked users.
+    #[arg(long)]
+    locked: bool,
+    /// Only users whose password has expired.
+    #[arg(long)]
+    expired: bool,
+    /// Only users waiting for approval.
+    #[arg(long)]
+    pending: bool,
+}
+
+impl Filter {
+    fn matches(&self, user: &User) -> bool {
+        let role_matches = match self.role {
+            Some(role) => user.action == LoginAction::Accept(role.into()),
+            None => true,
+        };
+        let locked = matches!(
+            user.action,
+            LoginAction::Denied(DeniedReason::AccountLocked { .. })
+        );
+        let expired = user.action == LoginAction::Denied(DeniedReason::PasswordExpired);
+        let pending = user.action == LoginAction::Denied(DeniedReason::PendingApproval);
+        role_matches
+            && (locked || !self.locked)
+            && (expired || !self.expired)
+            && (pending || !self.pending)
+    }
+}
+
+// One user, flattened for JSON and CSV output.
+#[derive(Serialize)]
+#[serde(crate = "authentication::serde")]
+struct Row<'a> {
+    username: &'a str,
+    state: &'static str,
+    role: Option<&'a Role>,
+    reason: Option<&'a str>,
+    display_name: Option<&'a str>,
+    email: Option<&'a str>,
+    // RFC 3339, in UTC.
+    created: Option<String>,
+    last_login: Option<String>,
+}
+
+impl<'a> From<&'a User> for Row<'a> {
+    fn from(user: &'a User) -> Self {
+        let (state, role, reason) = match &user.action {
+            LoginAction::Accept(role) => ("active", Some(role), None),
+            LoginAction::Denied(DeniedReason::PasswordExpired) => ("expired", None, None),
+            LoginAction::Denied(DeniedReason::PendingApproval) => ("pending", None, None),
+            LoginAction::Denied(DeniedReason::AccountLocked { reason }) => {
+                ("locked", None, Some(reason.as_str()))
+            }
+        };
+        Self {
+            username: user.username.as_str(),
+            state,
+            role,
+            reason,
+            display_name: user.display_name.as_deref(),
+            email: user.email.as_deref(),
+            created: user.created.map(timestamp),
+            last_login: user.last_login.map(timestamp),
+        }
+    }
+}
+
+pub fn list_users(users: &UserMap, filter: &Filter, format: Format) {
+    let mut users: Vec<&User> = users.values().filter(|user| filter.matches(user)).collect();
+    users.sort_by(|a, b| a.username.cmp(&b.username));
+    print_users(&users, format);
+}
+
+pub fn show_user(users: &UserMap, username: &Username, format: Format) {
+    match users.get(username) {
+        Some(user) => print_users(&[user], format),
+        None => {
+            println!("{username} doesn't exist");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_users(users: &[&User], format: Format) {
+    match format {
+        Format::Table => print_table(users),
+        Format::Json => {
+            let rows: Vec<Row> = users.iter().map(|user| Row::from(*user)).collect();
+            println!("{}", serde_json::to_string_pretty(&rows).unwrap());
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for user in users {
+                writer.serialize(Row::from(*user)).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+    }
+}
+
+fn print_table(users: &[&User]) {
+    // Left align the fields, padded to the widths given.
+    println!(
+        "{:<20}{:<20}{:<28}{:<18}{:<18}{:<20}",
+        "Username", "Display Name", "Email", "Created", "Last Login", "Login Action"
+    );
+    println!("{:-<124}", ""); // have a pad of `-` 124 chars wide.
+
+    users.iter().for_each(|user| {
+        let action = format!("{:?}", user.action);
+        let action = match user.action {
+            LoginAction::Accept(..) => action.green(),
+            LoginAction::Denied(..) => action.red(),
+        };
+        // To the minute, without the `T` and `Z` of the full timestamp.
+        let time = |time: Option<u64>| match time {
+            Some(time) => timestamp(time)[..16].replace('T', " "),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<20}{:<20}{:<28}{:<18}{:<18}{:<20}",
+            user.username,
+            user.display_name.as_deref().unwrap_or("-"),
+            user.email.as_deref().unwrap_or("-"),
+            time(user.created),
+            time(user.last_login),
+            action
+        );
+    });
+}
+
+// Seconds since the Unix epoch as a UTC date and time, e.g. `2023-03-18T09:30:00Z`.
+fn timestamp(seconds: u64) -> String {
+    let (days, time) = ((seconds / 86_400) as i64, seconds % 86_400);
+    // Howard Hinnant's `civil_from_days`: count in 400 year eras of 146097
+    // days, with years starting in March so the leap day comes last.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = era * 400 + year_of_era + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(1_679_131_845), "2023-03-18T09:30:45Z");
+        assert_eq!(timestamp(4_102_444_799), "2099-12-31T23:59:59Z");
+    }
+}
@@ -1,73 +1,317 @@
 use authentication::*;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use group::GroupCommands;
+use list::{Filter, Format};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use transfer::{Conflict, FileFormat};
+
+mod doctor;
+mod encrypt;
+mod group;
+mod list;
+mod remote;
+mod transfer;
+mod tui;
 
 #[derive(Parser)]
 #[command()] // The default command.
 struct Args {
     #[command(subcommand)] // Defining additional commands, which are defined in the enum.
     command: Option<Commands>,
+    /// Manage the users of a running tcp_login_server instead of the local users.json.
+    #[arg(long, global = true, value_name = "HOST:PORT")]
+    remote: Option<String>,
+    /// The admin to authenticate as with --remote; their password is prompted for.
+    #[arg(long, global = true, requires = "remote")]
+    admin: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// List all users.
-    List,
-    // Add a user.
+    /// List users, sorted by username.
+    List {
+        #[command(flatten)]
+        filter: Filter,
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Show a single user.
+    Show {
+        /// Username.
+        username: Username,
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Add a user. The password is prompted for, never taken from the command line.
     Add {
         /// Username.
         #[arg(long)]
-        username: String,
-        /// Password.
-        #[arg(long)]
-        password: String,
-        /// Optional - mark as limited user.
+        username: Username,
+        /// Role of the new user.
+        #[arg(long, value_enum, default_value_t = RoleArg::User)]
+        role: RoleArg,
+        /// Read the password from the first line of stdin instead of prompting.
         #[arg(long)]
-        limited: Option<bool>,
-        /// Optional - mark as admin.
-        #[arg(long)]
-        admin: Option<bool>,
+        password_stdin: bool,
     },
     /// Delete a user.
     Delete {
         /// Username.
-        username: String, // Here we demonstrate not using the `#[arg]`, we won't need the -- flags to access it.
+        username: Username, // Here we demonstrate not using the `#[arg]`, we won't need the -- flags to access it.
     },
-    /// Change a password
+    /// Change a password. The new password is prompted for.
     ChangePassword {
         /// Username.
-        username: String,
-        /// New Password.
-        new_password: String,
+        username: Username,
+        /// Read the new password from the first line of stdin instead of prompting.
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Set a user's display name or email. An empty value clears it.
+    SetProfile {
+        /// Username.
+        username: Username,
+        #[arg(long)]
+        display_name: Option<String>,
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// Change the role of an active user.
+    SetRole {
+        /// Username.
+        username: Username,
+        /// New role.
+        #[arg(value_enum)]
+        role: RoleArg,
+    },
+    /// Add users in bulk from a CSV or JSON file.
+    Import {
+        /// File of `username,state,role,reason,password,password_hash` records.
+        file: PathBuf,
+        /// File format; guessed from the extension if not given.
+        #[arg(long, value_enum)]
+        format: Option<FileFormat>,
+        /// What to do with users who already exist.
+        #[arg(long, value_enum, default_value_t = Conflict::Fail)]
+        on_conflict: Conflict,
+        /// Report what would change without saving anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write every user, with their password hash, in the import format.
+    Export {
+        /// Output file; stdout if not given.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// File format; guessed from the extension if not given, else JSON.
+        #[arg(long, value_enum)]
+        format: Option<FileFormat>,
+    },
+    /// Check the users file for problems left by hand edits.
+    Doctor {
+        /// Repair the problems that can be repaired automatically.
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Manage users interactively in a terminal UI.
+    Tui,
+    /// Manage groups, which give a role to all their members.
+    Group {
+        #[command(subcommand)]
+        command: GroupCommands,
+    },
+    /// Lock a user out.
+    Lock {
+        /// Username.
+        username: Username,
+        /// Shown to the user when they try to log in.
+        #[arg(long)]
+        reason: String,
+    },
+    /// Make a user change their password before they can log in again.
+    Expire {
+        /// Username.
+        username: Username,
+    },
+    /// Let a user who registered themselves log in.
+    Approve {
+        /// Username.
+        username: Username,
+        /// The role they get.
+        #[arg(long, value_enum, default_value_t = RoleArg::Limited)]
+        role: RoleArg,
+    },
+    /// Let a locked or expired user log in again.
+    Activate {
+        /// Username.
+        username: Username,
+        /// The role they come back with; the one they had before if not given.
+        #[arg(long, value_enum)]
+        role: Option<RoleArg>,
+    },
+    /// Encrypt users.json, with a key file (created if missing) or a passphrase.
+    Encrypt {
+        /// Use this key file instead of prompting for a passphrase.
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// Turn an encrypted users.json back into plain JSON.
+    Decrypt {
+        /// The key file it was encrypted with; otherwise the passphrase is prompted for.
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// Encrypt users.json again under a new key file or passphrase.
+    Rekey {
+        /// The current key file; otherwise the current passphrase is prompted for.
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// Create this key file and use it; otherwise a new passphrase is prompted for.
+        #[arg(long)]
+        new_key_file: Option<PathBuf>,
+    },
+    /// Send a user a link to pick a new password, valid for an hour.
+    ResetLink {
+        /// Username.
+        username: Username,
+        /// Where the web server is reached.
+        #[arg(long, default_value = "http://127.0.0.1:8000")]
+        base_url: String,
+        /// Append the link to this file instead of printing it.
+        #[arg(long)]
+        notify_file: Option<PathBuf>,
     },
 }
 
-type UserMap = HashMap<String, User>;
+// Clap's view of `Role`, so it can be parsed from the command line.
+#[derive(Clone, Copy, ValueEnum)]
+enum RoleArg {
+    Admin,
+    User,
+    Limited,
+}
+
+impl From<RoleArg> for Role {
+    fn from(role: RoleArg) -> Self {
+        match role {
+            RoleArg::Admin => Role::Admin,
+            RoleArg::User => Role::User,
+            RoleArg::Limited => Role::Limited,
+        }
+    }
+}
+
+type UserMap = HashMap<Username, User>;
 
 fn main() {
-    let mut users = get_users();
     let cli = Args::parse(); // Tell Clap to start reading incoming command structure.
-    match cli.command {
-        Some(Commands::List) => {
-            list_users(&users);
+    let command = match (cli.remote, cli.command) {
+        (Some(address), Some(command)) => return remote::run(&address, cli.admin, command),
+        (_, command) => command,
+    };
+    // The doctor reads the file itself, as it has to cope with files that don't
+    // load, and the encryption commands convert it without loading it.
+    let users = match command {
+        Some(
+            Commands::Doctor { .. }
+            | Commands::Encrypt { .. }
+            | Commands::Decrypt { .. }
+            | Commands::Rekey { .. },
+        ) => UserMap::new(),
+        // E.g. an encrypted file without the key.
+        _ => load_users("users.json").unwrap_or_else(|e| {
+            println!("Unable to read users.json: {e}, aborting");
+            std::process::exit(1);
+        }),
+    };
+    match command {
+        Some(Commands::List { filter, format }) => {
+            list::list_users(&users, &filter, format);
+        }
+        Some(Commands::Show { username, format }) => {
+            list::show_user(&users, &username, format);
         }
         Some(Commands::Add {
             username,
-            password,
-            limited,
-            admin,
+            role,
+            password_stdin,
         }) => {
-            add_user(&mut users, username, password, limited, admin);
+            let password = read_password(password_stdin);
+            update(|users| manage::add_user(users, &username, &password, role.into()));
         }
         Some(Commands::Delete { username }) => {
-            delete_user(&mut users, username);
+            let users = update(|users| {
+                manage::delete_user(users, &username)?;
+                Ok(users.clone())
+            });
+            drop_deleted_members(&users);
         }
         Some(Commands::ChangePassword {
             username,
-            new_password,
+            password_stdin,
+        }) => {
+            let new_password = read_password(password_stdin);
+            update(|users| manage::change_password(users, &username, &new_password));
+        }
+        Some(Commands::SetProfile {
+            username,
+            display_name,
+            email,
         }) => {
-            change_password(&mut users, username, new_password);
+            update(|users| {
+                manage::set_profile(users, &username, display_name.as_deref(), email.as_deref())
+            });
+        }
+        Some(Commands::SetRole { username, role }) => {
+            update(|users| manage::set_role(users, &username, role.into()));
+        }
+        Some(Commands::Import {
+            file,
+            format,
+            on_conflict,
+            dry_run,
+        }) => {
+            transfer::import_users(&users, &file, format, on_conflict, dry_run);
+        }
+        Some(Commands::Export { output, format }) => {
+            transfer::export_users(&users, output.as_deref(), format);
         }
+        Some(Commands::Tui) => {
+            if let Err(e) = tui::run(users) {
+                println!("{e}, aborting");
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Group { command }) => group::run(command, &users),
+        Some(Commands::Doctor { fix }) => {
+            doctor::doctor("users.json".as_ref(), fix);
+        }
+        Some(Commands::Lock { username, reason }) => {
+            update(|users| manage::lock(users, &username, &reason));
+        }
+        Some(Commands::Expire { username }) => {
+            update(|users| manage::expire(users, &username));
+        }
+        Some(Commands::Approve { username, role }) => {
+            update(|users| manage::approve(users, &username, role.into()));
+        }
+        Some(Commands::Activate { username, role }) => {
+            update(|users| manage::activate(users, &username, role.map(Into::into)));
+        }
+        Some(Commands::Encrypt { key_file }) => exit_on_error(encrypt::encrypt(key_file)),
+        Some(Commands::Decrypt { key_file }) => exit_on_error(encrypt::decrypt(key_file)),
+        Some(Commands::Rekey {
+            key_file,
+            new_key_file,
+        }) => exit_on_error(encrypt::rekey(key_file, new_key_file)),
+        Some(Commands::ResetLink {
+            username,
+            base_url,
+            notify_file,
+        }) => exit_on_error(send_reset_link(&users, &username, &base_url, notify_file)),
         None => {
             println!("Run with --help to see instructions");
             std::process::exit(0);
@@ -75,62 +319,79 @@ fn main() {
     }
 }
 
-fn list_users(users: &UserMap) {
-    use colored::Colorize;
-    println!("{:<20}{:<20}", "Username", "Login Action"); // Left align the field with pad of 20 chars.
-    println!("{:-<40}", ""); // have a pad of `-` 40 chars wide.
-
-    // Ignore the key with `_` in our call to for_each().
-    users.iter().for_each(|(_, user)| {
-        let action = format!("{:?}", user.action);
-        let action = match user.action {
-            LoginAction::Accept(..) => action.green(),
-            LoginAction::Denied(..) => action.red(),
-        };
-        println!("{:<20}{:<20}", user.username, action);
-    });
+// Prompt twice on the terminal without echoing, or read a single line from
+// stdin for scripts. Passwords never go on the command line, where `ps` and
+// the shell history would see them.
+fn read_password(from_stdin: bool) -> Password {
+    let result = if from_stdin {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password).map(|_| {
+            // Trim in place rather than copying, so only one buffer holds it.
+            let length = password.trim_end_matches(['\r', '\n']).len();
+            password.truncate(length);
+            password
+        })
+    } else {
+        rpassword::prompt_password("Password: ").and_then(|password| {
+            let confirmation = rpassword::prompt_password("Confirm password: ")?;
+            if password == confirmation {
+                Ok(password)
+            } else {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "the passwords don't match",
+                ))
+            }
+        })
+    };
+    result.map(Password::from).unwrap_or_else(|e| {
+        println!("Unable to read the password: {e}, aborting");
+        std::process::exit(1);
+    })
 }
 
-fn add_user(
-    users: &mut UserMap,
-    username: String,
-    password: String,
-    limited: Option<bool>,
-    admin: Option<bool>,
-) {
-    if users.contains_key(&username) {
-        println!("{username} already exists, aborting.");
-        return;
-    }
-    let action = LoginAction::Accept(if limited.is_some() {
-        // Giving an it statement as a parameter to a func.
-        Role::Limited
-    } else if admin.is_some() {
-        Role::Admin
-    } else {
-        Role::User
-    });
-    let user = User::new(&username, &password, action);
-    users.insert(username, user);
+// The tokens are kept where the web server, run from the same directory,
+// redeems them.
+fn send_reset_link(
+    users: &UserMap,
+    username: &Username,
+    base_url: &str,
+    notify_file: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let path = "reset_tokens.json";
+    let mut tokens = load_reset_tokens(path)?;
+    let token = issue_reset_token(&mut tokens, users, username, std::time::SystemTime::now())?;
+    save_reset_tokens(path, &tokens)?;
+    let notifier: Box<dyn Notifier> = match notify_file {
+        Some(path) => Box::new(FileNotifier::new(path)),
+        None => Box::new(StdoutNotifier),
+    };
+    notifier.send_reset_link(username, &reset_link(base_url, &token))?;
+    Ok(())
+}
 
-    save_users_file(users);
+fn exit_on_error<T>(result: Result<T, impl std::fmt::Display>) -> T {
+    result.unwrap_or_else(|e| {
+        println!("{e}, aborting");
+        std::process::exit(1);
+    })
 }
 
-fn delete_user(users: &mut UserMap, username: String) {
-    if !users.contains_key(&username) {
-        println!("{username} doesn't exist, aborting");
-        return;
+// Called after deleting users, so they don't linger in groups.
+fn drop_deleted_members(users: &UserMap) {
+    if let Err(e) = drop_deleted_members_from("groups.json", users) {
+        println!("Unable to update groups.json: {e}, aborting");
+        std::process::exit(1);
     }
-    users.remove(&username);
-    save_users_file(users);
 }
 
-fn change_password(users: &mut UserMap, username: String, new_password: String) {
-    // Use `get_mut` to return a mutable reference to the HashMap record.
-    if let Some(mut user) = users.get_mut(&username) {
-        user.password = hash_password(&new_password);
-        save_users_file(users);
-    } else {
-        println!("{username} doesn't exist, aborting");
-    }
+// Apply `change` to users.json as it is now, not as it was loaded, so nothing
+// web, the login server or `login` saved since is undone. If it can't be
+// applied, explain why and exit 1.
+fn update<T>(change: impl FnOnce(&mut UserMap) -> Result<T, UserError>) -> T {
+    let result = update_users_file("users.json", change).unwrap_or_else(|e| {
+        println!("Unable to save users.json: {e}, aborting");
+        std::process::exit(1);
+    });
+    exit_on_error(result)
 }
@@ -0,0 +1,67 @@
+// `--remote`: run a command on a tcp_login_server's users instead of the
+// local users.json, over the login server's admin protocol.
+use crate::{list, read_password, Commands};
+use authentication::{Password, User};
+use login_protocol::AdminClient;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn run(address: &str, admin: Option<String>, command: Commands) {
+    let Some(admin) = admin else {
+        println!("--remote needs --admin, aborting");
+        std::process::exit(1);
+    };
+    let password = rpassword::prompt_password(format!("Password for {admin}: "))
+        .map(Password::from)
+        .unwrap_or_else(|e| {
+            println!("Unable to read the password: {e}, aborting");
+            std::process::exit(1);
+        });
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let result = runtime.block_on(async {
+        let mut client = AdminClient::connect(address, TIMEOUT, &admin, &password).await?;
+        match command {
+            Commands::List { filter, format } => {
+                // The server never sends password hashes, and listing never shows them.
+                let users = client
+                    .list_users()
+                    .await?
+                    .into_iter()
+                    .map(|user| {
+                        let summary = User::with_hash(user.username.clone(), "", user.action);
+                        (user.username, summary)
+                    })
+                    .collect();
+                list::list_users(&users, &filter, format);
+            }
+            Commands::Add {
+                username,
+                role,
+                password_stdin,
+            } => {
+                let password = read_password(password_stdin);
+                client
+                    .add_user(username.as_str(), &password, role.into())
+                    .await?;
+            }
+            Commands::Delete { username } => client.delete_user(username.as_str()).await?,
+            Commands::ChangePassword {
+                username,
+                password_stdin,
+            } => {
+                let password = read_password(password_stdin);
+                client.change_password(username.as_str(), &password).await?;
+            }
+            _ => anyhow::bail!("only list, add, delete and change-password work with --remote"),
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        println!("{e}, aborting");
+        std::process::exit(1);
+    }
+}
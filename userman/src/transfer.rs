@@ -0,0 +1,397 @@
+// `import` and `export`: many users at once, as CSV or JSON records of
+// `username,state,role,reason,password,password_hash,display_name,email`.
+use crate::UserMap;
+use authentication::serde::{Deserialize, Serialize};
+use authentication::{
+    hash_password, is_password_hash, manage, unix_seconds, DeniedReason, LoginAction, Password,
+    Role, User, Username,
+};
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FileFormat {
+    Json,
+    Csv,
+}
+
+impl FileFormat {
+    // Guess from the file extension when `--format` isn't given.
+    fn for_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+// What to do with a record for a user who already exists.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Conflict {
+    Skip,
+    Overwrite,
+    Fail,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(crate = "authentication::serde", rename_all = "lowercase")]
+enum State {
+    #[default]
+    Active,
+    Locked,
+    Expired,
+    Pending,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "authentication::serde")]
+struct Record {
+    username: String,
+    // Defaults to active.
+    state: Option<State>,
+    // Defaults to `User` for active users. For denied ones, the role they get
+    // back when activated.
+    role: Option<Role>,
+    reason: Option<String>,
+    // Exactly one of these: a plaintext password to hash, or an existing hash.
+    password: Option<Password>,
+    password_hash: Option<String>,
+    display_name: Option<String>,
+    email: Option<String>,
+}
+
+impl Record {
+    fn to_user(&self) -> Result<User, String> {
+        let username = Username::new(&self.username).map_err(|e| e.to_string())?;
+        let action = match self.state.unwrap_or_default() {
+            State::Active => LoginAction::Accept(self.role.clone().unwrap_or(Role::User)),
+            State::Expired => LoginAction::Denied(DeniedReason::PasswordExpired),
+            State::Pending => LoginAction::Denied(DeniedReason::PendingApproval),
+            State::Locked => LoginAction::Denied(DeniedReason::AccountLocked {
+                reason: self.reason.clone().unwrap_or_default(),
+            }),
+        };
+        let hash = match (&self.password, &self.password_hash) {
+            (Some(password), None) => {
+                manage::validate_password(password).map_err(|e| e.to_string())?;
+                hash_password(password)
+            }
+            (None, Some(hash)) if is_password_hash(hash) => hash.clone(),
+            (None, Some(_)) => return Err("password_hash is not a valid hash".to_string()),
+            _ => return Err("needs exactly one of password or password_hash".to_string()),
+        };
+        if let Some(email) = &self.email {
+            manage::validate_email(email).map_err(|e| e.to_string())?;
+        }
+        let previous_role = match action {
+            LoginAction::Accept(_) => None,
+            LoginAction::Denied(_) => self.role.clone(),
+        };
+        let mut user = User::with_hash(username, &hash, action);
+        user.previous_role = previous_role;
+        user.display_name = self.display_name.clone();
+        user.email = self.email.clone();
+        Ok(user)
+    }
+
+    fn from_user(user: &User) -> Self {
+        let (state, role, reason) = match &user.action {
+            LoginAction::Accept(role) => (State::Active, Some(role.clone()), None),
+            LoginAction::Denied(DeniedReason::PasswordExpired) => {
+                (State::Expired, user.previous_role.clone(), None)
+            }
+            LoginAction::Denied(DeniedReason::PendingApproval) => {
+                (State::Pending, user.previous_role.clone(), None)
+            }
+            LoginAction::Denied(DeniedReason::AccountLocked { reason }) => (
+                State::Locked,
+                user.previous_role.clone(),
+                Some(reason.clone()),
+            ),
+        };
+        Self {
+            username: user.username.to_string(),
+            state: Some(state),
+            role,
+            reason,
+            password: None,
+            password_hash: Some(user.password_hash().to_string()),
+            display_name: user.display_name.clone(),
+            email: user.email.clone(),
+        }
+    }
+}
+
+fn read_records(path: &Path, format: FileFormat) -> Result<Vec<Record>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    match format {
+        FileFormat::Json => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+        FileFormat::Csv => csv::Reader::from_reader(contents.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string()),
+    }
+}
+
+fn pick_format(path: &Path, format: Option<FileFormat>) -> FileFormat {
+    format
+        .or_else(|| FileFormat::for_path(path))
+        .unwrap_or_else(|| {
+            println!(
+                "Unable to tell the format of {}, use --format",
+                path.display()
+            );
+            std::process::exit(1);
+        })
+}
+
+// Check every record before touching anything: the import either applies in
+// full, with a single save at the end, or not at all. It's checked against
+// users.json as it is when saved, so nothing saved meanwhile is undone.
+pub fn import_users(
+    users: &UserMap,
+    path: &Path,
+    format: Option<FileFormat>,
+    conflict: Conflict,
+    dry_run: bool,
+) {
+    let records = read_records(path, pick_format(path, format)).unwrap_or_else(|e| {
+        println!("Unable to read {}: {e}, aborting", path.display());
+        std::process::exit(1);
+    });
+
+    let now = SystemTime::now();
+    let merge_or_exit = |users: &UserMap| {
+        merge(users, &records, conflict, now).unwrap_or_else(|errors| {
+            errors.iter().for_each(|e| println!("{e}"));
+            println!("{} problem(s) found, nothing imported", errors.len());
+            std::process::exit(1);
+        })
+    };
+    let counts = if dry_run {
+        merge_or_exit(users).1
+    } else {
+        crate::update(|users| {
+            let (imported, counts) = merge_or_exit(users);
+            *users = imported;
+            Ok(counts)
+        })
+    };
+    println!(
+        "{} added, {} overwritten, {} skipped",
+        counts.added, counts.overwritten, counts.skipped
+    );
+    if dry_run {
+        println!("Dry run, nothing saved");
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Counts {
+    added: usize,
+    overwritten: usize,
+    skipped: usize,
+}
+
+// `users` with the records applied, or every record that can't be. New users
+// are created `now`; overwritten ones keep when they were created and last
+// logged in, which the records don't carry.
+fn merge(
+    users: &UserMap,
+    records: &[Record],
+    conflict: Conflict,
+    now: SystemTime,
+) -> Result<(UserMap, Counts), Vec<String>> {
+    let mut imported = users.clone();
+    let mut errors = Vec::new();
+    let mut counts = Counts::default();
+    let mut seen = HashSet::new();
+    for (i, record) in records.iter().enumerate() {
+        let mut user = match record.to_user() {
+            Ok(user) => user,
+            Err(e) => {
+                errors.push(format!("record {}: {}: {e}", i + 1, record.username));
+                continue;
+            }
+        };
+        // Which of the two was meant is for a person to say.
+        if !seen.insert(user.username.clone()) {
+            errors.push(format!(
+                "record {}: {} appears more than once in the file",
+                i + 1,
+                user.username
+            ));
+            continue;
+        }
+        match users.get(&user.username) {
+            Some(existing) => match conflict {
+                Conflict::Skip => {
+                    counts.skipped += 1;
+                    continue;
+                }
+                Conflict::Overwrite => {
+                    counts.overwritten += 1;
+                    user.created = existing.created;
+                    user.last_login = existing.last_login;
+                }
+                Conflict::Fail => {
+                    errors.push(format!(
+                        "record {}: {} already exists",
+                        i + 1,
+                        user.username
+                    ));
+                    continue;
+                }
+            },
+            None => {
+                counts.added += 1;
+                user.created = Some(unix_seconds(now));
+            }
+        }
+        imported.insert(user.username.clone(), user);
+    }
+    if errors.is_empty() {
+        Ok((imported, counts))
+    } else {
+        Err(errors)
+    }
+}
+
+fn write_records(records: &[Record], format: FileFormat) -> anyhow::Result<String> {
+    match format {
+        FileFormat::Json => Ok(serde_json::to_string_pretty(records)? + "\n"),
+        FileFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for record in records {
+                writer.serialize(record)?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+    }
+}
+
+// Write every user, sorted by username, to `path` or stdout.
+pub fn export_users(users: &UserMap, path: Option<&Path>, format: Option<FileFormat>) {
+    let format = match path {
+        Some(path) => pick_format(path, format),
+        None => format.unwrap_or(FileFormat::Json),
+    };
+    let mut records: Vec<Record> = users.values().map(Record::from_user).collect();
+    records.sort_by(|a, b| a.username.cmp(&b.username));
+
+    let output = write_records(&records, format).unwrap_or_else(|e| {
+        println!("Unable to export the users: {e}, aborting");
+        std::process::exit(1);
+    });
+    match path {
+        Some(path) => std::fs::write(path, output).unwrap_or_else(|e| {
+            println!("Unable to write {}: {e}", path.display());
+            std::process::exit(1);
+        }),
+        None => print!("{output}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_round_trip() {
+        let mut user = User::new(
+            Username::new("kevin").unwrap(),
+            &Password::from("password"),
+            LoginAction::Denied(DeniedReason::AccountLocked {
+                reason: "Contact HR!".to_string(),
+            }),
+        );
+        user.email = Some("kevin@example.com".to_string());
+        user.previous_role = Some(Role::Admin);
+        let record = Record::from_user(&user);
+        let back = record.to_user().unwrap();
+        assert_eq!(back.username, user.username);
+        assert_eq!(back.email, user.email);
+        assert_eq!(back.password_hash(), user.password_hash());
+        assert_eq!(back.action, user.action);
+        assert_eq!(back.previous_role, Some(Role::Admin));
+
+        let csv = "username,state,role,reason,password,password_hash\n\
+                   mantou,,Limited,,longpassword,\n\
+                   baga,active,,,,not-a-hash\n";
+        let records: Vec<Record> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let mantou = records[0].to_user().unwrap();
+        assert_eq!(mantou.action, LoginAction::Accept(Role::Limited));
+        assert_eq!(
+            mantou.password_hash(),
+            hash_password(&Password::from("longpassword"))
+        );
+        assert!(records[1].to_user().is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let now = SystemTime::now();
+        let mut users = UserMap::new();
+        let mantou = Username::new("mantou").unwrap();
+        let mut existing = User::new(
+            mantou.clone(),
+            &Password::from("password"),
+            LoginAction::Accept(Role::User),
+        );
+        existing.created = Some(1_679_131_845);
+        existing.last_login = Some(1_679_131_900);
+        users.insert(mantou.clone(), existing);
+
+        let record = |username: &str| Record {
+            username: username.to_string(),
+            state: None,
+            role: Some(Role::Admin),
+            reason: None,
+            password: Some(Password::from("longpassword")),
+            password_hash: None,
+            display_name: None,
+            email: None,
+        };
+        let (imported, counts) = merge(
+            &users,
+            &[record("mantou"), record("baga")],
+            Conflict::Overwrite,
+            now,
+        )
+        .unwrap();
+        assert_eq!(
+            counts,
+            Counts {
+                added: 1,
+                overwritten: 1,
+                skipped: 0
+            }
+        );
+        assert_eq!(imported[&mantou].action, LoginAction::Accept(Role::Admin));
+        assert_eq!(imported[&mantou].created, Some(1_679_131_845));
+        assert_eq!(imported[&mantou].last_login, Some(1_679_131_900));
+        let baga = Username::new("baga").unwrap();
+        assert_eq!(imported[&baga].created, Some(unix_seconds(now)));
+        assert_eq!(imported[&baga].last_login, None);
+
+        // A user twice in the file is an error, not an overwrite, whatever
+        // `--on-conflict` says.
+        let errors = merge(
+            &users,
+            &[record("baga"), record(" Baga")],
+            Conflict::Overwrite,
+            now,
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec!["record 2: baga appears more than once in the file"]
+        );
+    }
+}
@@ -0,0 +1,478 @@
+// `tui`: the same operations as the subcommands, driven from the keyboard for
+// help-desk staff. Key handling only changes `App` and the files; drawing
+// happens in `run`, so the whole flow can be tested without a terminal.
+use crate::UserMap;
+use authentication::{
+    drop_deleted_members_from, manage, update_users_file, DeniedReason, LoginAction, Password,
+    Role, User, UserError, Username,
+};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Clear, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+use std::path::PathBuf;
+
+const HELP: &str =
+    "↑/↓ select  a add  d delete  p reset password  l lock/unlock/approve  r change role  q quit";
+
+// What a text prompt is collecting, and what it leads to once entered.
+enum Step {
+    AddUsername,
+    AddPassword {
+        username: Username,
+    },
+    AddConfirm {
+        username: Username,
+        password: Password,
+    },
+    ResetPassword {
+        username: Username,
+    },
+    ResetConfirm {
+        username: Username,
+        password: Password,
+    },
+    LockReason {
+        username: Username,
+    },
+}
+
+impl Step {
+    fn prompt(&self) -> String {
+        match self {
+            Self::AddUsername => "New username".to_string(),
+            Self::AddPassword { username } => format!("Password for {username}"),
+            Self::AddConfirm { .. } | Self::ResetConfirm { .. } => "Confirm password".to_string(),
+            Self::ResetPassword { username } => format!("New password for {username}"),
+            Self::LockReason { username } => format!("Why is {username} locked?"),
+        }
+    }
+
+    fn secret(&self) -> bool {
+        !matches!(self, Self::AddUsername | Self::LockReason { .. })
+    }
+}
+
+// The edits that lose something, so they wait for a yes.
+enum Edit {
+    Delete(Username),
+    ResetPassword(Username, Password),
+    Lock(Username, String),
+}
+
+impl Edit {
+    fn question(&self) -> String {
+        match self {
+            Self::Delete(username) => format!("Delete {username}?"),
+            Self::ResetPassword(username, _) => format!("Reset the password of {username}?"),
+            Self::Lock(username, _) => format!("Lock {username} out?"),
+        }
+    }
+}
+
+enum Mode {
+    Browse,
+    Input { step: Step, value: String },
+    Confirm(Edit),
+}
+
+struct App {
+    // The users file and groups file, changed as each edit is made.
+    path: PathBuf,
+    groups_path: PathBuf,
+    // As of the last edit, or when the TUI opened.
+    users: UserMap,
+    // Usernames in display order.
+    names: Vec<Username>,
+    table: TableState,
+    mode: Mode,
+    status: String,
+    quit: bool,
+}
+
+impl App {
+    fn new(path: PathBuf, groups_path: PathBuf, users: UserMap) -> Self {
+        let mut app = Self {
+            path,
+            groups_path,
+            users,
+            names: Vec::new(),
+            table: TableState::default(),
+            mode: Mode::Browse,
+            status: String::new(),
+            quit: false,
+        };
+        app.refresh();
+        app
+    }
+
+    // Re-sort after a change, keeping the selection on the same row if possible.
+    fn refresh(&mut self) {
+        self.names = self.users.keys().cloned().collect();
+        self.names.sort();
+        let selected = match self.table.selected() {
+            _ if self.names.is_empty() => None,
+            Some(i) => Some(i.min(self.names.len() - 1)),
+            None => Some(0),
+        };
+        self.table.select(selected);
+    }
+
+    fn selected(&self) -> Option<Username> {
+        self.table.selected().map(|i| self.names[i].clone())
+    }
+
+    fn select(&mut self, username: &Username) {
+        let index = self.names.iter().position(|name| name == username);
+        self.table.select(index);
+    }
+
+    fn step_selection(&mut self, by: isize) {
+        if let Some(i) = self.table.selected() {
+            let last = self.names.len() - 1;
+            self.table
+                .select(Some(i.saturating_add_signed(by).min(last)));
+        }
+    }
+
+    // Make `change` to the users file as it is now, not to our copy, so
+    // nothing saved by others since the TUI opened is undone. Our copy is
+    // then the file as saved, their changes included.
+    fn apply(
+        &mut self,
+        done: String,
+        change: impl FnOnce(&mut UserMap) -> Result<(), UserError>,
+    ) -> bool {
+        let result = update_users_file(&self.path, |users| {
+            change(users)?;
+            Ok(users.clone())
+        });
+        match result {
+            Ok(Ok(users)) => {
+                self.users = users;
+                self.status = done;
+                self.refresh();
+                true
+            }
+            Ok(Err(e)) => {
+                self.status = e.to_string();
+                false
+            }
+            Err(e) => {
+                self.status = format!("Unable to save {}: {e}", self.path.display());
+                false
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyCode) {
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => self.browse(key),
+            Mode::Input { step, value } => self.input(key, step, value),
+            Mode::Confirm(edit) => self.confirm(key, edit),
+        }
+    }
+
+    fn browse(&mut self, key: KeyCode) {
+        self.status.clear();
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.step_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.step_selection(-1),
+            KeyCode::Char('a') => self.ask(Step::AddUsername),
+            _ => {
+                if let Some(username) = self.selected() {
+                    self.act_on(key, username);
+                }
+            }
+        }
+    }
+
+    // The keys that need a user selected.
+    fn act_on(&mut self, key: KeyCode, username: Username) {
+        match key {
+            KeyCode::Char('d') => self.mode = Mode::Confirm(Edit::Delete(username)),
+            KeyCode::Char('p') => self.ask(Step::ResetPassword { username }),
+            KeyCode::Char('l') => match self.users[&username].action {
+                LoginAction::Accept(_) => self.ask(Step::LockReason { username }),
+                LoginAction::Denied(DeniedReason::PendingApproval) => {
+                    self.apply(format!("{username} is approved"), |users| {
+                        manage::approve(users, &username, Role::Limited)
+                    });
+                }
+                LoginAction::Denied(_) => {
+                    self.apply(format!("{username} can log in again"), |users| {
+                        manage::activate(users, &username, None)
+                    });
+                }
+            },
+            KeyCode::Char('r') => {
+                let role = match self.users[&username].action {
+                    LoginAction::Accept(Role::Admin) => Role::User,
+                    LoginAction::Accept(Role::User) => Role::Limited,
+                    _ => Role::Admin,
+                };
+                let done = format!("{username} is now {role:?}");
+                self.apply(done, |users| manage::set_role(users, &username, role));
+            }
+            _ => {}
+        }
+    }
+
+    fn ask(&mut self, step: Step) {
+        self.mode = Mode::Input {
+            step,
+            value: String::new(),
+        };
+    }
+
+    fn input(&mut self, key: KeyCode, step: Step, mut value: String) {
+        match key {
+            KeyCode::Esc => return,
+            KeyCode::Enter => return self.entered(step, value),
+            KeyCode::Backspace => {
+                value.pop();
+            }
+            KeyCode::Char(c) => value.push(c),
+            _ => {}
+        }
+        self.mode = Mode::Input { step, value };
+    }
+
+    fn entered(&mut self, step: Step, value: String) {
+        match step {
+            Step::AddUsername => match Username::new(&value) {
+                Ok(username) => self.ask(Step::AddPassword { username }),
+                Err(e) => self.status = e.to_string(),
+            },
+            Step::AddPassword { username } => {
+                let password = Password::from(value);
+                match manage::validate_password(&password) {
+                    Ok(()) => self.ask(Step::AddConfirm { username, password }),
+                    Err(e) => self.status = e.to_string(),
+                }
+            }
+            Step::AddConfirm { username, password } if password.expose() == value => {
+                self.apply(format!("{username} added"), |users| {
+                    manage::add_user(users, &username, &password, Role::User)
+                });
+                self.select(&username);
+            }
+            Step::ResetPassword { username } => {
+                let password = Password::from(value);
+                match manage::validate_password(&password) {
+                    Ok(()) => self.ask(Step::ResetConfirm { username, password }),
+                    Err(e) => self.status = e.to_string(),
+                }
+            }
+            Step::ResetConfirm { username, password } if password.expose() == value => {
+                self.mode = Mode::Confirm(Edit::ResetPassword(username, password));
+            }
+            Step::AddConfirm { .. } | Step::ResetConfirm { .. } => {
+                self.status = "the passwords don't match".to_string();
+            }
+            Step::LockReason { username } => {
+                self.mode = Mode::Confirm(Edit::Lock(username, value));
+            }
+        }
+    }
+
+    fn confirm(&mut self, key: KeyCode, edit: Edit) {
+        if !matches!(key, KeyCode::Char('y') | KeyCode::Char('Y')) {
+            self.status = "Cancelled".to_string();
+            return;
+        }
+        match edit {
+            Edit::Delete(username) => {
+                let deleted = self.apply(format!("{username} deleted"), |users| {
+                    manage::delete_user(users, &username).map(|_| ())
+                });
+                // So they don't linger in groups.
+                if deleted {
+                    if let Err(e) = drop_deleted_members_from(&self.groups_path, &self.users) {
+                        self.status = format!("{username} deleted, but unable to save groups: {e}");
+                    }
+                }
+            }
+            Edit::ResetPassword(username, password) => {
+                self.apply(format!("{username}'s password was reset"), |users| {
+                    manage::change_password(users, &username, &password)
+                });
+            }
+            Edit::Lock(username, reason) => {
+                self.apply(format!("{username} is locked"), |users| {
+                    manage::lock(users, &username, &reason)
+                });
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [list, help, status] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        // Colored like `userman list`: green gets in, red doesn't.
+        let rows = self.names.iter().map(|name| {
+            let user: &User = &self.users[name];
+            let color = match user.action {
+                LoginAction::Accept(..) => Color::Green,
+                LoginAction::Denied(..) => Color::Red,
+            };
+            Row::new(vec![
+                user.username.to_string(),
+                format!("{:?}", user.action),
+            ])
+            .style(Style::default().fg(color))
+        });
+        let table = Table::new(rows, [Constraint::Length(20), Constraint::Fill(1)])
+            .header(Row::new(["Username", "Login Action"]).style(Modifier::BOLD))
+            .block(Block::bordered().title(" Users "))
+            .row_highlight_style(Modifier::REVERSED);
+        frame.render_stateful_widget(table, list, &mut self.table);
+        frame.render_widget(Paragraph::new(HELP).style(Color::DarkGray), help);
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+
+        match &self.mode {
+            Mode::Browse => {}
+            Mode::Input { step, value } => {
+                let shown = match step.secret() {
+                    true => "*".repeat(value.chars().count()),
+                    false => value.clone(),
+                };
+                let block = Block::bordered().title(format!(" {} ", step.prompt()));
+                popup(frame, Paragraph::new(shown).block(block));
+            }
+            Mode::Confirm(edit) => {
+                let text = vec![
+                    Line::from(edit.question()),
+                    Line::from("y: yes, any key: no"),
+                ];
+                let block = Block::bordered().title(" Confirm ").style(Color::Yellow);
+                popup(frame, Paragraph::new(text).block(block));
+            }
+        }
+    }
+}
+
+// Draw `dialog` in a box in the middle of the screen.
+fn popup(frame: &mut Frame, dialog: Paragraph) {
+    let area = centered(frame.area(), 50, 4);
+    frame.render_widget(Clear, area);
+    frame.render_widget(dialog, area);
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+pub fn run(users: UserMap) -> std::io::Result<()> {
+    let mut app = App::new("users.json".into(), "groups.json".into(), users);
+    let mut terminal = ratatui::init();
+    let result = (|| {
+        while !app.quit {
+            terminal.draw(|frame| app.draw(frame))?;
+            if let Event::Key(KeyEvent {
+                code,
+                kind: KeyEventKind::Press,
+                ..
+            }) = event::read()?
+            {
+                app.handle_key(code);
+            }
+        }
+        Ok(())
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn type_in(app: &mut App, text: &str) {
+        text.chars().for_each(|c| app.handle_key(KeyCode::Char(c)));
+        app.handle_key(KeyCode::Enter);
+    }
+
+    #[test]
+    fn test_tui_keys() {
+        let temp = |name: &str| {
+            std::env::temp_dir().join(format!("tui-{name}-{}.json", std::process::id()))
+        };
+        let (path, groups_path) = (temp("users"), temp("groups"));
+        let mantou = Username::new("mantou").unwrap();
+        let mut app = App::new(path.clone(), groups_path.clone(), HashMap::new());
+        app.handle_key(KeyCode::Char('a'));
+        type_in(&mut app, "Mantou");
+        type_in(&mut app, "password");
+        type_in(&mut app, "password");
+        assert!(authentication::load_users(&path)
+            .unwrap()
+            .contains_key(&mantou));
+        assert_eq!(app.selected(), Some(mantou.clone()));
+
+        // Users added by others since stay, and show up after the next edit.
+        let baga = Username::new("baga").unwrap();
+        authentication::update_users_file(&path, |users| {
+            manage::add_user(users, &baga, &Password::from("password"), Role::User)
+        })
+        .unwrap()
+        .unwrap();
+
+        // Limited, Admin, then back to User.
+        app.handle_key(KeyCode::Char('r'));
+        assert_eq!(
+            app.users[&mantou].action,
+            LoginAction::Accept(Role::Limited)
+        );
+        assert!(app.users.contains_key(&baga));
+        app.select(&mantou);
+        app.handle_key(KeyCode::Char('r'));
+        app.handle_key(KeyCode::Char('r'));
+        assert_eq!(app.users[&mantou].action, LoginAction::Accept(Role::User));
+
+        // Locking waits for a yes.
+        app.handle_key(KeyCode::Char('l'));
+        type_in(&mut app, "Contact HR!");
+        app.handle_key(KeyCode::Char('n'));
+        assert_eq!(app.users[&mantou].action, LoginAction::Accept(Role::User));
+        app.handle_key(KeyCode::Char('l'));
+        type_in(&mut app, "Contact HR!");
+        app.handle_key(KeyCode::Char('y'));
+        assert_eq!(
+            app.users[&mantou].action,
+            LoginAction::Denied(DeniedReason::AccountLocked {
+                reason: "Contact HR!".to_string()
+            })
+        );
+        app.handle_key(KeyCode::Char('l'));
+        assert_eq!(app.users[&mantou].action, LoginAction::Accept(Role::User));
+
+        app.handle_key(KeyCode::Char('p'));
+        type_in(&mut app, "new password");
+        type_in(&mut app, "mismatch");
+        assert_eq!(app.status, "the passwords don't match");
+
+        app.handle_key(KeyCode::Char('d'));
+        app.handle_key(KeyCode::Char('y'));
+        assert!(!app.users.contains_key(&mantou));
+        assert_eq!(app.selected(), Some(baga));
+        app.handle_key(KeyCode::Char('q'));
+        assert!(app.quit);
+        std::fs::remove_file(path).unwrap();
+    }
+}
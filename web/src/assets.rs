@@ -0,0 +1,62 @@
+// Pages, scripts and styles are compiled into the binary, so the web server
+// runs from any directory and never needs a CDN.
+use rocket::http::ContentType;
+use rocket::Route;
+
+pub const LOGIN_PAGE: &str = include_str!("../static/login.html");
+pub const PROTECTED_PAGE: &str = include_str!("../static/protected.html");
+pub const PASSWORD_PAGE: &str = include_str!("../static/password.html");
+pub const ADMIN_PAGE: &str = include_str!("../static/admin.html");
+pub const RESET_PAGE: &str = include_str!("../static/reset.html");
+pub const API_DOCS_PAGE: &str = include_str!("../static/api-docs.html");
+
+// Everything served under `/static/`.
+const STATIC_FILES: &[(&str, &str)] = &[
+    ("app.js", include_str!("../static/app.js")),
+    ("login.js", include_str!("../static/login.js")),
+    ("protected.js", include_str!("../static/protected.js")),
+    ("password.js", include_str!("../static/password.js")),
+    ("admin.js", include_str!("../static/admin.js")),
+    ("reset.js", include_str!("../static/reset.js")),
+    ("api-docs.js", include_str!("../static/api-docs.js")),
+    ("style.css", include_str!("../static/style.css")),
+];
+
+fn find(name: &str) -> Option<(ContentType, &'static str)> {
+    let (_, body) = STATIC_FILES.iter().find(|(file, _)| *file == name)?;
+    let extension = name.rsplit('.').next()?;
+    let content_type = ContentType::from_extension(extension).unwrap_or(ContentType::Plain);
+    Some((content_type, body))
+}
+
+#[get("/static/<name>")]
+pub fn static_file(name: &str) -> Option<(ContentType, &'static str)> {
+    find(name)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![static_file]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_files() {
+        assert_eq!(find("app.js").unwrap().0, ContentType::JavaScript);
+        assert_eq!(find("style.css").unwrap().0, ContentType::CSS);
+        assert!(find("../Cargo.toml").is_none());
+        // No page should reach out to the internet.
+        for page in [
+            LOGIN_PAGE,
+            PROTECTED_PAGE,
+            PASSWORD_PAGE,
+            ADMIN_PAGE,
+            RESET_PAGE,
+            API_DOCS_PAGE,
+        ] {
+            assert!(!page.contains("http://") && !page.contains("https://"));
+        }
+    }
+}
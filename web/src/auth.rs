@@ -0,0 +1,54 @@
+use crate::config::{AuthMode, Config};
+use crate::users::UserStore;
+use authentication::{manage, LoginAction, Password, Username};
+use login_protocol::LoginPool;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+// Where login attempts are checked, picked by `auth_mode`. The login server
+// applies group roles itself; locally they come from `groups_file`.
+pub enum Authenticator {
+    Local { groups_file: PathBuf },
+    Remote(LoginPool),
+}
+
+impl Authenticator {
+    pub fn new(config: &Config) -> Self {
+        match config.auth_mode {
+            AuthMode::Local => Self::Local {
+                groups_file: config.groups_file.clone().into(),
+            },
+            AuthMode::Remote => Self::Remote(LoginPool::new(
+                &config.auth_server,
+                Duration::from_millis(config.auth_timeout_ms),
+                config.auth_pool_size,
+            )),
+        }
+    }
+
+    // An `Err` means the login server couldn't be asked at all.
+    pub async fn login(
+        &self,
+        store: &UserStore,
+        username: &str,
+        password: &Password,
+    ) -> anyhow::Result<Option<LoginAction>> {
+        match self {
+            Self::Local { groups_file } => {
+                // Read on every login, so group changes apply at once.
+                let groups = authentication::load_groups(groups_file)?;
+                let action =
+                    authentication::login_with_groups(&store.users(), &groups, username, password);
+                if let (Some(LoginAction::Accept(_)), Ok(username)) =
+                    (&action, Username::new(username))
+                {
+                    // The login stands even if the time can't be saved.
+                    let _ = store
+                        .update(|users| manage::record_login(users, &username, SystemTime::now()));
+                }
+                Ok(action)
+            }
+            Self::Remote(pool) => pool.login(username, password).await,
+        }
+    }
+}
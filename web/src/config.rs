@@ -0,0 +1,87 @@
+// Settings read from Rocket's figment: `Rocket.toml` or `ROCKET_*` environment
+// variables, next to Rocket's own `port`, `address`, ...
+use rocket::serde::Deserialize;
+use rocket::{Orbit, Rocket};
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Config {
+    // The users file the admin API edits, and `local` mode logs in against.
+    #[serde(default = "default_users_file")]
+    pub users_file: String,
+    // The groups users get roles from, in `local` mode, and are dropped from
+    // when deleted.
+    #[serde(default = "default_groups_file")]
+    pub groups_file: String,
+    #[serde(default)]
+    pub auth_mode: AuthMode,
+    // Where the tcp_login_server listens, in `remote` mode.
+    #[serde(default = "default_auth_server")]
+    pub auth_server: String,
+    #[serde(default = "default_auth_timeout_ms")]
+    pub auth_timeout_ms: u64,
+    // How many idle connections to the login server to keep open.
+    #[serde(default = "default_auth_pool_size")]
+    pub auth_pool_size: usize,
+    // Password reset tokens, hashed.
+    #[serde(default = "default_reset_tokens_file")]
+    pub reset_tokens_file: String,
+    // Where this server is reached, for the links in reset messages.
+    #[serde(default = "default_reset_base_url")]
+    pub reset_base_url: String,
+    // Reset links are appended to this file if set, otherwise printed.
+    #[serde(default)]
+    pub reset_notify_file: Option<String>,
+    // Whether cookies are marked `Secure`, see `secure_cookies`.
+    #[serde(default)]
+    pub secure_cookies: Option<bool>,
+}
+
+// Browsers drop `Secure` cookies sent over plain HTTP (to anywhere but
+// localhost), so cookies are only marked so when the site is served over
+// HTTPS: by Rocket's own TLS, unless configured otherwise, e.g. behind a proxy
+// that terminates TLS.
+pub fn secure_cookies(rocket: &Rocket<Orbit>) -> bool {
+    let configured = rocket
+        .state::<Config>()
+        .and_then(|config| config.secure_cookies);
+    configured.unwrap_or_else(|| rocket.config().tls_enabled())
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum AuthMode {
+    // Check passwords in-process, against `users_file`: a single binary.
+    Local,
+    // Ask a tcp_login_server: separate web and authentication tiers.
+    #[default]
+    Remote,
+}
+
+fn default_users_file() -> String {
+    "users.json".to_string()
+}
+
+fn default_groups_file() -> String {
+    "groups.json".to_string()
+}
+
+fn default_auth_server() -> String {
+    login_protocol::DEFAULT_ADDRESS.to_string()
+}
+
+fn default_auth_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_auth_pool_size() -> usize {
+    8
+}
+
+fn default_reset_tokens_file() -> String {
+    "reset_tokens.json".to_string()
+}
+
+fn default_reset_base_url() -> String {
+    "http://127.0.0.1:8000".to_string()
+}
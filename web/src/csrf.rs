@@ -0,0 +1,107 @@
+use crate::config;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Cookie, SameSite, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::content::RawHtml;
+use rocket::Data;
+
+// Double-submit scheme: every client gets a random token in a cookie, the
+// pages we serve embed the same token, and state-changing requests must echo
+// it back in a header. A cross-site form can make the browser send the cookie,
+// but it can't read the token to put it in the header.
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+const CSRF_PLACEHOLDER: &str = "{{csrf_token}}";
+
+// Hands out a token cookie to any client that doesn't have one yet.
+pub struct CsrfFairing;
+
+#[rocket::async_trait]
+impl Fairing for CsrfFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF token cookie",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        if request.cookies().get(CSRF_COOKIE).is_none() {
+            let cookie = Cookie::build(CSRF_COOKIE, crate::new_token())
+                .path("/")
+                .http_only(true)
+                .secure(config::secure_cookies(request.rocket()))
+                .same_site(SameSite::Strict)
+                .finish();
+            request.cookies().add(cookie);
+        }
+    }
+}
+
+// The current client's token, for rendering into pages.
+pub struct CsrfToken(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // `get_pending` also sees the cookie the fairing just added.
+        match request.cookies().get_pending(CSRF_COOKIE) {
+            Some(cookie) => Outcome::Success(Self(cookie.value().to_string())),
+            None => Outcome::Failure((Status::Forbidden, ())),
+        }
+    }
+}
+
+impl CsrfToken {
+    // Fill in the token placeholder of one of the embedded pages.
+    pub fn render(&self, page: &str) -> RawHtml<String> {
+        RawHtml(page.replace(CSRF_PLACEHOLDER, &self.0))
+    }
+}
+
+// Request guard for state-changing routes: the header must match the cookie,
+// otherwise the request is rejected with 403.
+pub struct CsrfProtected;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfProtected {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let cookie = request.cookies().get(CSRF_COOKIE);
+        let header = request.headers().get_one(CSRF_HEADER);
+        match (cookie, header) {
+            (Some(cookie), Some(header)) if tokens_match(cookie.value(), header) => {
+                Outcome::Success(Self)
+            }
+            _ => Outcome::Failure((Status::Forbidden, ())),
+        }
+    }
+}
+
+// Compare without bailing out at the first difference, so response timing
+// doesn't leak how much of a guess was right.
+fn tokens_match(a: &str, b: &str) -> bool {
+    !a.is_empty()
+        && a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc123", "abc124"));
+        assert!(!tokens_match("abc123", "abc12"));
+        assert!(!tokens_match("", "abc"));
+        assert!(!tokens_match("", ""));
+    }
+}
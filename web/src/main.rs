@@ -1,50 +1,259 @@
 #[macro_use]
 extern crate rocket;
 
-use rocket::fs::NamedFile;
+use auth::Authenticator;
+use authentication::{DeniedReason, LoginAction, Password, Role, Username};
+use config::Config;
+use csrf::{CsrfFairing, CsrfProtected, CsrfToken};
+use rocket::http::{CookieJar, Status};
+use rocket::response::{content::RawHtml, Redirect};
 use rocket::serde::{json::Json, Deserialize, Serialize};
-use rocket::tokio::io::AsyncWriteExt;
-use std::net::TcpStream;
+use rocket::State;
+use session::{AdminUser, AuthenticatedUser, SessionCookie, Sessions};
+use users::UserStore;
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+pub mod assets;
+pub mod auth;
+pub mod config;
+pub mod csrf;
+pub mod openapi;
+pub mod reset;
+pub mod session;
+pub mod users;
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[serde(crate = "rocket::serde")]
 pub struct Login {
     username: String,
-    password: String,
+    password: Password,
+}
+
+// The body returned by `/api/login`. The HTTP status carries the outcome too,
+// so the client can branch on either. `token` is the session id, for clients
+// that authenticate with a bearer header rather than the cookie.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct LoginResponse {
+    accepted: bool,
+    role: Option<Role>,
+    denied_reason: Option<DeniedReason>,
+    token: Option<String>,
+}
+
+impl LoginResponse {
+    fn from_action(action: Option<LoginAction>) -> (Status, Self) {
+        let mut response = Self {
+            accepted: false,
+            role: None,
+            denied_reason: None,
+            token: None,
+        };
+        let status = match action {
+            Some(LoginAction::Accept(role)) => {
+                response.accepted = true;
+                response.role = Some(role);
+                Status::Ok
+            }
+            Some(LoginAction::Denied(reason)) => {
+                let status = match reason {
+                    DeniedReason::PasswordExpired | DeniedReason::PendingApproval => {
+                        Status::Forbidden
+                    }
+                    DeniedReason::AccountLocked { .. } => Status::Locked,
+                };
+                response.denied_reason = Some(reason);
+                status
+            }
+            None => Status::Unauthorized, // Unknown user or wrong password.
+        };
+        (status, response)
+    }
+
+    fn unavailable() -> (Status, Self) {
+        let (_, response) = Self::from_action(None);
+        (Status::ServiceUnavailable, response)
+    }
+}
+
+// 32 random bytes, hex encoded.
+pub fn new_token() -> String {
+    use rand::RngCore;
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
 
 #[get("/")]
-pub async fn login_page<'a>() -> NamedFile {
-    NamedFile::open("login.html").await.unwrap()
+pub fn login_page(csrf: CsrfToken) -> RawHtml<String> {
+    csrf.render(assets::LOGIN_PAGE)
 }
 /*fn index() -> &'static str {
     "Hello, world!"
 }*/
 
+#[utoipa::path(
+    tag = "session",
+    request_body = Login,
+    params(("X-CSRF-Token" = String, Header, description = "The page's CSRF token")),
+    responses(
+        (status = 200, description = "Logged in, the session cookie is set", body = LoginResponse),
+        (status = 401, description = "Unknown user or wrong password", body = LoginResponse),
+        (status = 403, description = "Password expired, or missing CSRF token", body = LoginResponse),
+        (status = 423, description = "Account locked", body = LoginResponse),
+        (status = 503, description = "The login server is unavailable", body = LoginResponse),
+    )
+)]
 #[post("/api/login", data = "<user>")]
-pub async fn login(user: Json<Login>) {
-    use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
-    use rocket::tokio::net::TcpStream;
+pub async fn login(
+    _csrf: CsrfProtected,
+    user: Json<Login>,
+    auth: &State<Authenticator>,
+    store: &State<UserStore>,
+    sessions: &State<Sessions>,
+    cookie: SessionCookie<'_>,
+) -> (Status, Json<LoginResponse>) {
+    let (status, mut response) = match auth.login(store, &user.username, &user.password).await {
+        Ok(action) => LoginResponse::from_action(action),
+        Err(e) => {
+            println!("Login server unavailable: {e}");
+            LoginResponse::unavailable()
+        }
+    };
+    // Only a valid username can have been accepted.
+    if let (Some(role), Ok(username)) = (response.role.clone(), Username::new(&user.username)) {
+        let token = sessions.create(&username, role);
+        cookie.set(token.clone());
+        response.token = Some(token);
+    }
+    (status, Json(response))
+}
+
+#[utoipa::path(
+    tag = "session",
+    params(("X-CSRF-Token" = String, Header, description = "The page's CSRF token")),
+    responses((status = 204, description = "Logged out, the session cookie is cleared"))
+)]
+#[post("/api/logout")]
+pub fn logout(_csrf: CsrfProtected, sessions: &State<Sessions>, cookies: &CookieJar<'_>) -> Status {
+    if let Some(cookie) = cookies.get(session::SESSION_COOKIE) {
+        sessions.remove(cookie.value());
+    }
+    session::clear_session_cookie(cookies);
+    Status::NoContent
+}
+
+#[utoipa::path(
+    tag = "session",
+    responses(
+        (status = 200, description = "The signed-in user", body = AuthenticatedUser),
+        (status = 401, description = "Not signed in", body = users::ErrorBody),
+    ),
+    security(("session_cookie" = []), ("bearer" = []))
+)]
+#[get("/api/me")]
+pub fn me(user: AuthenticatedUser) -> Json<AuthenticatedUser> {
+    Json(user)
+}
 
-    use authentication::*;
-    let login_attempt = user.0;
+// Pages for signed-in users only. Anonymous visitors fail the guard with 401,
+// which the catcher below turns into a trip to the login form.
+#[get("/protected")]
+pub fn protected_page(_user: AuthenticatedUser, csrf: CsrfToken) -> RawHtml<String> {
+    csrf.render(assets::PROTECTED_PAGE)
+}
 
-    let mut stream = TcpStream::connect("127.0.0.1:8123").await.unwrap();
-    let message = bincode::serialize(&login_attempt).unwrap();
-    stream.write_all(&message).await.unwrap();
+#[get("/password")]
+pub fn password_page(_user: AuthenticatedUser, csrf: CsrfToken) -> RawHtml<String> {
+    csrf.render(assets::PASSWORD_PAGE)
+}
 
-    let mut buf = vec![0; 1024];
-    let n = stream.read(&mut buf).await.unwrap();
-    let response: Option<LoginAction> = bincode::deserialize(&buf[0..n]).unwrap();
+// The user table itself is filled in from `/api/users`, which checks the role
+// again; this only keeps non-admins from seeing an empty shell.
+#[get("/admin")]
+pub fn admin_page(_admin: AdminUser, csrf: CsrfToken) -> RawHtml<String> {
+    csrf.render(assets::ADMIN_PAGE)
+}
 
-    println!("{response:?}");
+#[catch(401)]
+fn login_redirect() -> Redirect {
+    Redirect::to(uri!(login_page))
+}
+
+// API clients get errors as JSON rather than Rocket's html pages.
+#[catch(default)]
+fn api_catcher(status: Status, _request: &rocket::Request) -> users::ApiError {
+    users::ApiError::new(status, status.reason().unwrap_or("error"))
 }
 
 #[launch]
 fn rocket() -> _ {
-    rocket::build().mount("/", routes![login_page, login])
+    let rocket = rocket::build();
+    let config: Config = rocket.figment().extract().expect("invalid configuration");
+    let store = UserStore::open(&config.users_file)
+        .unwrap_or_else(|e| panic!("Unable to read {}: {e}", config.users_file));
+    rocket
+        .manage(Sessions::default())
+        .manage(store)
+        .manage(Authenticator::new(&config))
+        .manage(reset::ResetTokens::new(&config))
+        .manage(config)
+        .attach(CsrfFairing)
+        .mount(
+            "/",
+            routes![
+                login_page,
+                login,
+                logout,
+                me,
+                protected_page,
+                password_page,
+                admin_page
+            ],
+        )
+        .mount("/", users::routes())
+        .mount("/", reset::routes())
+        .mount("/", assets::routes())
+        .mount("/", openapi::routes())
+        .register("/", catchers![login_redirect])
+        .register("/api", catchers![api_catcher])
 }
 
 // fn main() {
 //     println!("Hello, world!");
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_status() {
+        let (status, response) = LoginResponse::from_action(Some(LoginAction::Accept(Role::User)));
+        assert_eq!(status, Status::Ok);
+        assert!(response.accepted);
+        assert_eq!(response.role, Some(Role::User));
+
+        let (status, response) = LoginResponse::from_action(None);
+        assert_eq!(status, Status::Unauthorized);
+        assert!(!response.accepted);
+        assert_eq!(response.token, None);
+
+        let (status, _) =
+            LoginResponse::from_action(Some(LoginAction::Denied(DeniedReason::PasswordExpired)));
+        assert_eq!(status, Status::Forbidden);
+
+        let (status, response) =
+            LoginResponse::from_action(Some(LoginAction::Denied(DeniedReason::AccountLocked {
+                reason: "Contact HR!".to_string(),
+            })));
+        assert_eq!(status, Status::Locked);
+        assert!(matches!(
+            response.denied_reason,
+            Some(DeniedReason::AccountLocked { .. })
+        ));
+
+        let (status, _) = LoginResponse::unavailable();
+        assert_eq!(status, Status::ServiceUnavailable);
+    }
+}
@@ -0,0 +1,134 @@
+// The OpenAPI document for `/api`, generated from the route annotations and the
+// serde types, so it can't drift from what the handlers actually accept.
+use crate::{reset, session, users};
+use rocket::response::content::RawHtml;
+use rocket::serde::json::Json;
+use rocket::Route;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Insecure Secure Server",
+        description = "Login sessions and user administration."
+    ),
+    paths(
+        crate::login,
+        crate::logout,
+        crate::me,
+        users::list_users,
+        users::add_user,
+        users::delete_user,
+        users::change_password,
+        users::set_action,
+        users::change_own_password,
+        reset::send_reset_link,
+        reset::reset_password,
+    ),
+    modifiers(&SessionAuth),
+    tags(
+        (name = "session", description = "Logging in and out"),
+        (name = "users", description = "User management, admins only"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SessionAuth;
+
+impl Modify for SessionAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session_cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(session::SESSION_COOKIE))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[get("/openapi.json")]
+pub fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// A small viewer for the document, served from the binary like every other page.
+#[get("/api-docs")]
+pub fn api_docs() -> RawHtml<&'static str> {
+    RawHtml(crate::assets::API_DOCS_PAGE)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![openapi_json, api_docs]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::serde_json;
+    use std::collections::BTreeSet;
+
+    // `/api/users/<username>` as OpenAPI spells it: `/api/users/{username}`.
+    fn openapi_path(rocket_path: &str) -> String {
+        rocket_path
+            .split('/')
+            .map(|segment| match segment.strip_prefix('<') {
+                Some(name) => format!("{{{}}}", name.trim_end_matches('>').trim_end_matches("..")),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // Fails when an `/api` route is added, removed or changed without the
+    // document following.
+    #[test]
+    fn test_spec_matches_routes() {
+        let routes: BTreeSet<(String, String)> = crate::rocket()
+            .routes()
+            .filter(|route| route.uri.path().starts_with("/api/"))
+            .map(|route| {
+                (
+                    route.method.as_str().to_lowercase(),
+                    openapi_path(route.uri.path()),
+                )
+            })
+            .collect();
+
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let documented: BTreeSet<(String, String)> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect();
+
+        assert_eq!(routes, documented);
+    }
+
+    #[test]
+    fn test_spec_schemas() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        for schema in [
+            "Login",
+            "LoginResponse",
+            "LoginAction",
+            "Role",
+            "DeniedReason",
+        ] {
+            assert!(
+                spec["components"]["schemas"].get(schema).is_some(),
+                "{schema} is missing from the spec"
+            );
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// Password reset links. An admin asks for one, the configured `Notifier`
+// delivers it, and the user picks a new password on `/reset` without signing
+// in. The token handling is `authentication`'s; this keeps the tokens file.
+use crate::assets;
+use crate::config::Config;
+use crate::csrf::{CsrfProtected, CsrfToken};
+use crate::session::{AdminUser, Sessions};
+use crate::users::{ApiError, ErrorBody, UserStore};
+use authentication::{
+    manage, FileNotifier, Notifier, Password, ResetToken, StdoutNotifier, UserError, Username,
+};
+use parking_lot::Mutex;
+use rocket::http::Status;
+use rocket::response::content::RawHtml;
+use rocket::serde::{json::Json, Deserialize};
+use rocket::{Route, State};
+use std::path::PathBuf;
+use std::time::SystemTime;
+use utoipa::ToSchema;
+
+pub struct ResetTokens {
+    path: PathBuf,
+    base_url: String,
+    notifier: Box<dyn Notifier>,
+    // Held across each load, change and save of the file.
+    lock: Mutex<()>,
+}
+
+impl ResetTokens {
+    pub fn new(config: &Config) -> Self {
+        let notifier: Box<dyn Notifier> = match &config.reset_notify_file {
+            Some(path) => Box::new(FileNotifier::new(path)),
+            None => Box::new(StdoutNotifier),
+        };
+        Self {
+            path: config.reset_tokens_file.clone().into(),
+            base_url: config.reset_base_url.clone(),
+            notifier,
+            lock: Mutex::new(()),
+        }
+    }
+
+    // Like `UserStore::update`, the file is only written when `change` succeeds.
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&mut Vec<ResetToken>) -> Result<T, UserError>,
+    ) -> Result<T, ApiError> {
+        let _lock = self.lock.lock();
+        let unavailable = |e: std::io::Error| {
+            println!("Unable to use {}: {e}", self.path.display());
+            ApiError::new(Status::InternalServerError, "unable to save reset tokens")
+        };
+        let mut tokens = authentication::load_reset_tokens(&self.path).map_err(unavailable)?;
+        let result = change(&mut tokens)?;
+        authentication::save_reset_tokens(&self.path, &tokens).map_err(unavailable)?;
+        Ok(result)
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordReset {
+    token: String,
+    password: Password,
+}
+
+#[utoipa::path(
+    tag = "users",
+    params(
+        ("username" = String, Path, description = "The user who forgot their password"),
+        ("X-CSRF-Token" = String, Header, description = "The page's CSRF token"),
+    ),
+    responses(
+        (status = 202, description = "A reset link was sent to the user, valid for an hour"),
+        (status = 400, description = "Not a valid username", body = ErrorBody),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 403, description = "Not an admin, or missing CSRF token", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+        (status = 500, description = "The link couldn't be sent", body = ErrorBody),
+    ),
+    security(("session_cookie" = []), ("bearer" = []))
+)]
+#[post("/api/users/<username>/reset-link")]
+pub fn send_reset_link(
+    _admin: AdminUser,
+    _csrf: CsrfProtected,
+    store: &State<UserStore>,
+    tokens: &State<ResetTokens>,
+    username: &str,
+) -> Result<Status, ApiError> {
+    let username = Username::new(username)?;
+    let token = tokens.update(|tokens| {
+        authentication::issue_reset_token(tokens, &store.users(), &username, SystemTime::now())
+    })?;
+    let link = authentication::reset_link(&tokens.base_url, &token);
+    tokens
+        .notifier
+        .send_reset_link(&username, &link)
+        .map_err(|e| {
+            println!("Unable to send the reset link of {username}: {e}");
+            ApiError::new(Status::InternalServerError, "unable to send the reset link")
+        })?;
+    Ok(Status::Accepted)
+}
+
+// Anyone holding a valid token may set the password: the token is the proof.
+#[utoipa::path(
+    tag = "session",
+    request_body = PasswordReset,
+    params(("X-CSRF-Token" = String, Header, description = "The page's CSRF token")),
+    responses(
+        (status = 204, description = "Password reset, every session of the user is ended"),
+        (status = 400, description = "The new password is too weak", body = ErrorBody),
+        (status = 403, description = "Invalid or expired reset token, or missing CSRF token", body = ErrorBody),
+    )
+)]
+#[post("/api/reset-password", data = "<reset>")]
+pub fn reset_password(
+    _csrf: CsrfProtected,
+    store: &State<UserStore>,
+    sessions: &State<Sessions>,
+    tokens: &State<ResetTokens>,
+    reset: Json<PasswordReset>,
+) -> Result<Status, ApiError> {
+    // Checked first, so a weak password doesn't use up the token.
+    manage::validate_password(&reset.password)?;
+    let username = tokens.update(|tokens| {
+        authentication::take_reset_token(tokens, &reset.token, SystemTime::now())
+    })?;
+    store.update(|users| manage::reset_password(users, &username, &reset.password))?;
+    sessions.remove_user(&username);
+    Ok(Status::NoContent)
+}
+
+#[get("/reset")]
+pub fn reset_page(csrf: CsrfToken) -> RawHtml<String> {
+    csrf.render(assets::RESET_PAGE)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![send_reset_link, reset_password, reset_page]
+}
@@ -0,0 +1,173 @@
+use crate::config;
+use authentication::{Role, Username};
+use parking_lot::RwLock;
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const SESSION_COOKIE: &str = "session";
+const SESSION_TTL: Duration = Duration::from_secs(8 * 60 * 60);
+
+struct Session {
+    user: AuthenticatedUser,
+    expires: Instant,
+}
+
+// Server-side session store, managed by Rocket. The cookie only carries the
+// random session id, everything else stays on the server.
+#[derive(Default)]
+pub struct Sessions(RwLock<HashMap<String, Session>>);
+
+impl Sessions {
+    // Start a session and return its id.
+    pub fn create(&self, username: &Username, role: Role) -> String {
+        let token = crate::new_token();
+        let now = Instant::now();
+        let mut sessions = self.0.write();
+        sessions.retain(|_, session| session.expires > now); // Drop anything stale while we hold the lock.
+        sessions.insert(
+            token.clone(),
+            Session {
+                user: AuthenticatedUser {
+                    username: username.to_string(),
+                    role,
+                },
+                expires: now + SESSION_TTL,
+            },
+        );
+        token
+    }
+
+    pub fn get(&self, token: &str) -> Option<AuthenticatedUser> {
+        self.0
+            .read()
+            .get(token)
+            .filter(|session| session.expires > Instant::now())
+            .map(|session| session.user.clone())
+    }
+
+    pub fn remove(&self, token: &str) {
+        self.0.write().remove(token);
+    }
+
+    // End every session belonging to `username`, e.g. after an admin locks them.
+    pub fn remove_user(&self, username: &Username) {
+        self.0
+            .write()
+            .retain(|_, session| session.user.username != username.as_str());
+    }
+}
+
+// Request guard for starting a session in the browser.
+pub struct SessionCookie<'r> {
+    cookies: &'r CookieJar<'r>,
+    secure: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SessionCookie<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self {
+            cookies: request.cookies(),
+            secure: config::secure_cookies(request.rocket()),
+        })
+    }
+}
+
+impl SessionCookie<'_> {
+    pub fn set(&self, token: String) {
+        let cookie = Cookie::build(SESSION_COOKIE, token)
+            .path("/")
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(SameSite::Strict)
+            .max_age(rocket::time::Duration::seconds(SESSION_TTL.as_secs() as i64))
+            .finish();
+        self.cookies.add(cookie);
+    }
+}
+
+pub fn clear_session_cookie(cookies: &CookieJar<'_>) {
+    cookies.remove(Cookie::build(SESSION_COOKIE, "").path("/").finish());
+}
+
+// The session id from the cookie, or from an `Authorization: Bearer` header
+// for API clients that took the token from the login response.
+pub fn session_token(request: &Request<'_>) -> Option<String> {
+    if let Some(cookie) = request.cookies().get(SESSION_COOKIE) {
+        return Some(cookie.value().to_string());
+    }
+    request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
+// Request guard: succeeds only for requests carrying a live session.
+#[derive(Clone, Debug, Serialize, utoipa::ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AuthenticatedUser {
+    pub username: String,
+    pub role: Role,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let sessions = match request.rocket().state::<Sessions>() {
+            Some(sessions) => sessions,
+            None => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+        match session_token(request).and_then(|token| sessions.get(&token)) {
+            Some(user) => Outcome::Success(user),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+// Request guard for the admin API: a live session with `Role::Admin`.
+pub struct AdminUser(pub AuthenticatedUser);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.guard::<AuthenticatedUser>().await {
+            Outcome::Success(user) if user.role == Role::Admin => Outcome::Success(Self(user)),
+            Outcome::Success(_) => Outcome::Failure((Status::Forbidden, ())),
+            Outcome::Failure(failure) => Outcome::Failure(failure),
+            Outcome::Forward(forward) => Outcome::Forward(forward),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions() {
+        let sessions = Sessions::default();
+        let token = sessions.create(&Username::new("adam").unwrap(), Role::Admin);
+        let user = sessions.get(&token).unwrap();
+        assert_eq!(user.username, "adam");
+        assert_eq!(user.role, Role::Admin);
+        assert!(sessions.get("not-a-session").is_none());
+
+        sessions.remove(&token);
+        assert!(sessions.get(&token).is_none());
+
+        let mike = Username::new("mike").unwrap();
+        let token = sessions.create(&mike, Role::User);
+        sessions.remove_user(&mike);
+        assert!(sessions.get(&token).is_none());
+    }
+}
@@ -0,0 +1,412 @@
+// Admin REST API for user management. The operations and validation are the
+// ones `userman` uses, from `authentication::manage`.
+use crate::config::Config;
+use crate::csrf::CsrfProtected;
+use crate::session::{AdminUser, AuthenticatedUser, Sessions};
+use authentication::{manage, LoginAction, Password, Role, User, UserError, Username};
+use parking_lot::{Mutex, RwLock};
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Request, Route, State};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use utoipa::ToSchema;
+
+// The users file, loaded again whenever it changes on disk, e.g. when
+// `userman` or the login server's admin locked someone.
+pub struct UserStore {
+    path: PathBuf,
+    users: RwLock<HashMap<Username, User>>,
+    // Of the file, when `users` was loaded.
+    stamp: Mutex<Stamp>,
+}
+
+// A file's modification time and size, `None` if there is no file.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> std::io::Result<Stamp> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(Some((metadata.modified()?, metadata.len()))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl UserStore {
+    // A missing file is a fresh install. Any other error must stop the
+    // server: starting empty would overwrite the file on the first change.
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        // Stamped before loading, so a change made meanwhile is picked up by
+        // the next `users` rather than missed.
+        let loaded = stamp(&path)?;
+        if loaded.is_none() {
+            println!("{} doesn't exist, starting empty", path.display());
+        }
+        let users = load(&path, loaded)?;
+        Ok(Self {
+            path,
+            users: RwLock::new(users),
+            stamp: Mutex::new(loaded),
+        })
+    }
+
+    // As the file is now: only costs a look at it if it didn't change.
+    pub fn users(&self) -> parking_lot::RwLockReadGuard<'_, HashMap<Username, User>> {
+        if let Err(e) = self.refresh() {
+            // Keep going with what we have, as the login server does.
+            println!("Unable to reload {}: {e}", self.path.display());
+        }
+        self.users.read()
+    }
+
+    fn refresh(&self) -> std::io::Result<()> {
+        let mut loaded = self.stamp.lock();
+        let current = stamp(&self.path)?;
+        if current != *loaded {
+            *self.users.write() = load(&self.path, current)?;
+            *loaded = current;
+        }
+        Ok(())
+    }
+
+    // Apply `change` to the file as it is now rather than to our copy, so
+    // whatever `userman` or the login server saved since isn't undone. Our
+    // copy is only replaced once the file has been saved, so a failed write
+    // never leaves memory and disk disagreeing.
+    pub fn update<T>(
+        &self,
+        change: impl FnOnce(&mut HashMap<Username, User>) -> Result<T, UserError>,
+    ) -> Result<T, ApiError> {
+        let mut users = self.users.write();
+        let (result, updated) = authentication::update_users_file(&self.path, |updated| {
+            Ok((change(updated)?, updated.clone()))
+        })
+        .map_err(|e| {
+            println!("Unable to save {}: {e}", self.path.display());
+            ApiError::new(Status::InternalServerError, "unable to save users")
+        })??;
+        *users = updated;
+        Ok(result)
+    }
+}
+
+// Only a missing users file is empty: a missing key file is an error.
+fn load(path: &Path, stamp: Stamp) -> std::io::Result<HashMap<Username, User>> {
+    match stamp {
+        None => Ok(HashMap::new()),
+        Some(_) => authentication::load_users(path),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ErrorBody {
+    error: String,
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    status: Status,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: Status, message: &str) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<UserError> for ApiError {
+    fn from(e: UserError) -> Self {
+        let status = match e {
+            UserError::AlreadyExists(_)
+            | UserError::GroupAlreadyExists(_)
+            | UserError::AlreadyMember { .. } => Status::Conflict,
+            UserError::NotFound(_) | UserError::GroupNotFound(_) | UserError::NotMember { .. } => {
+                Status::NotFound
+            }
+            UserError::InvalidUsername(_)
+            | UserError::WeakPassword
+            | UserError::InvalidEmail(_) => Status::BadRequest,
+            UserError::InvalidResetToken => Status::Forbidden,
+            UserError::NotActive(_) | UserError::NotPending(_) | UserError::NoPreviousRole(_) => {
+                Status::Conflict
+            }
+        };
+        Self::new(status, &e.to_string())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let body = ErrorBody {
+            error: self.message,
+        };
+        (self.status, Json(body)).respond_to(request)
+    }
+}
+
+// What the API shows of a user: never the password hash.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct UserSummary {
+    username: String,
+    action: LoginAction,
+    // What unlocking a denied user gives back, if known.
+    previous_role: Option<Role>,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewUser {
+    username: String,
+    password: Password,
+    role: Role,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewPassword {
+    password: Password,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordChange {
+    current_password: Password,
+    new_password: Password,
+}
+
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, description = "Every user, sorted by username", body = Vec<UserSummary>),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 403, description = "Not an admin, or missing CSRF token", body = ErrorBody),
+    ),
+    security(("session_cookie" = []), ("bearer" = []))
+)]
+#[get("/api/users")]
+pub fn list_users(_admin: AdminUser, store: &State<UserStore>) -> Json<Vec<UserSummary>> {
+    let mut users: Vec<UserSummary> = store
+        .users()
+        .values()
+        .map(|user| UserSummary {
+            username: user.username.to_string(),
+            action: user.action.clone(),
+            previous_role: user.previous_role.clone(),
+        })
+        .collect();
+    users.sort_by(|a, b| a.username.cmp(&b.username));
+    Json(users)
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = NewUser,
+    params(("X-CSRF-Token" = String, Header, description = "The page's CSRF token")),
+    responses(
+        (status = 201, description = "User added"),
+        (status = 400, description = "Invalid username or password", body = ErrorBody),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 403, description = "Not an admin, or missing CSRF token", body = ErrorBody),
+        (status = 409, description = "The user already exists", body = ErrorBody),
+    ),
+    security(("session_cookie" = []), ("bearer" = []))
+)]
+#[post("/api/users", data = "<user>")]
+pub fn add_user(
+    _admin: AdminUser,
+    _csrf: CsrfProtected,
+    store: &State<UserStore>,
+    user: Json<NewUser>,
+) -> Result<Status, ApiError> {
+    let user = user.into_inner();
+    let username = Username::new(&user.username)?;
+    store.update(|users| manage::add_user(users, &username, &user.password, user.role))?;
+    Ok(Status::Created)
+}
+
+#[utoipa::path(
+    tag = "users",
+    params(
+        ("username" = String, Path, description = "The user to change"),
+        ("X-CSRF-Token" = String, Header, description = "The page's CSRF token"),
+    ),
+    responses(
+        (status = 204, description = "User deleted, their sessions are ended"),
+        (status = 400, description = "Not a valid username", body = ErrorBody),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 403, description = "Not an admin, or missing CSRF token", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+        (status = 500, description = "Deleted, but still in their groups", body = ErrorBody),
+    ),
+    security(("session_cookie" = []), ("bearer" = []))
+)]
+#[delete("/api/users/<username>")]
+pub fn delete_user(
+    _admin: AdminUser,
+    _csrf: CsrfProtected,
+    store: &State<UserStore>,
+    sessions: &State<Sessions>,
+    config: &State<Config>,
+    username: &str,
+) -> Result<Status, ApiError> {
+    let username = Username::new(username)?;
+    store.update(|users| manage::delete_user(users, &username))?;
+    sessions.remove_user(&username);
+    // So nobody given the username later inherits their groups.
+    authentication::drop_deleted_members_from(&config.groups_file, &store.users()).map_err(
+        |e| {
+            println!("Unable to save {}: {e}", config.groups_file);
+            ApiError::new(
+                Status::InternalServerError,
+                "deleted, but unable to save groups",
+            )
+        },
+    )?;
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = NewPassword,
+    params(
+        ("username" = String, Path, description = "The user to change"),
+        ("X-CSRF-Token" = String, Header, description = "The page's CSRF token"),
+    ),
+    responses(
+        (status = 204, description = "Password changed, the user's sessions are ended"),
+        (status = 400, description = "Invalid username, or the password is too weak", body = ErrorBody),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 403, description = "Not an admin, or missing CSRF token", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+    ),
+    security(("session_cookie" = []), ("bearer" = []))
+)]
+#[put("/api/users/<username>/password", data = "<password>")]
+pub fn change_password(
+    _admin: AdminUser,
+    _csrf: CsrfProtected,
+    store: &State<UserStore>,
+    sessions: &State<Sessions>,
+    username: &str,
+    password: Json<NewPassword>,
+) -> Result<Status, ApiError> {
+    let username = Username::new(username)?;
+    store.update(|users| manage::change_password(users, &username, &password.password))?;
+    sessions.remove_user(&username);
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    tag = "users",
+    request_body = LoginAction,
+    params(
+        ("username" = String, Path, description = "The user to change"),
+        ("X-CSRF-Token" = String, Header, description = "The page's CSRF token"),
+    ),
+    responses(
+        (status = 204, description = "Login action changed, the user's sessions are ended"),
+        (status = 400, description = "Not a valid username", body = ErrorBody),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 403, description = "Not an admin, or missing CSRF token", body = ErrorBody),
+        (status = 404, description = "No such user", body = ErrorBody),
+    ),
+    security(("session_cookie" = []), ("bearer" = []))
+)]
+#[put("/api/users/<username>/action", data = "<action>")]
+pub fn set_action(
+    _admin: AdminUser,
+    _csrf: CsrfProtected,
+    store: &State<UserStore>,
+    sessions: &State<Sessions>,
+    username: &str,
+    action: Json<LoginAction>,
+) -> Result<Status, ApiError> {
+    let username = Username::new(username)?;
+    store.update(|users| manage::set_action(users, &username, action.into_inner()))?;
+    sessions.remove_user(&username);
+    Ok(Status::NoContent)
+}
+
+// Any signed-in user may change their own password, given the current one.
+#[utoipa::path(
+    tag = "session",
+    request_body = PasswordChange,
+    params(("X-CSRF-Token" = String, Header, description = "The page's CSRF token")),
+    responses(
+        (status = 204, description = "Password changed, every session of the user is ended"),
+        (status = 400, description = "The new password is too weak", body = ErrorBody),
+        (status = 401, description = "Not signed in", body = ErrorBody),
+        (status = 403, description = "Wrong current password, or missing CSRF token", body = ErrorBody),
+    ),
+    security(("session_cookie" = []), ("bearer" = []))
+)]
+#[put("/api/me/password", data = "<change>")]
+pub fn change_own_password(
+    user: AuthenticatedUser,
+    _csrf: CsrfProtected,
+    store: &State<UserStore>,
+    sessions: &State<Sessions>,
+    change: Json<PasswordChange>,
+) -> Result<Status, ApiError> {
+    if authentication::login(&store.users(), &user.username, &change.current_password).is_none() {
+        return Err(ApiError::new(
+            Status::Forbidden,
+            "the current password is incorrect",
+        ));
+    }
+    let username = Username::new(&user.username)?;
+    store.update(|users| manage::change_password(users, &username, &change.new_password))?;
+    sessions.remove_user(&username);
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_users,
+        add_user,
+        delete_user,
+        change_password,
+        set_action,
+        change_own_password
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_store_reloads() {
+        let path = std::env::temp_dir().join(format!("web-users-{}.json", std::process::id()));
+        let password = Password::from("password");
+        let (adam, mantou) = (
+            Username::new("adam").unwrap(),
+            Username::new("mantou").unwrap(),
+        );
+        let mut users = HashMap::new();
+        manage::add_user(&mut users, &adam, &password, Role::Admin).unwrap();
+        authentication::save_users(&path, &users).unwrap();
+        let store = UserStore::open(&path).unwrap();
+        assert!(store.users().contains_key(&adam));
+
+        // Changed by someone else, e.g. `userman`.
+        manage::lock(&mut users, &adam, "Contact HR!").unwrap();
+        manage::add_user(&mut users, &mantou, &password, Role::User).unwrap();
+        authentication::save_users(&path, &users).unwrap();
+        assert!(authentication::login(&store.users(), "adam", &password)
+            .is_some_and(|action| action != LoginAction::Accept(Role::Admin)));
+        assert!(store.users().contains_key(&mantou));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(store.users().is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+const ROLES = ["Admin", "User", "Limited"];
+
+async function loadUsers() {
+    const result = await api("GET", "/api/users");
+    if (!result.ok) {
+        showMessage(errorText(result, "Unable to load users"));
+        return;
+    }
+    const tbody = document.getElementById("users");
+    tbody.replaceChildren(...result.data.map(userRow));
+}
+
+// Build rows with DOM calls rather than html strings: usernames and lock
+// reasons are user data.
+function userRow(user) {
+    const row = document.createElement("tr");
+    const cell = (child) => {
+        const td = document.createElement("td");
+        td.append(child);
+        row.append(td);
+    };
+    const accepted = Boolean(user.action.Accept);
+
+    cell(user.username);
+
+    const status = document.createElement("span");
+    status.className = accepted ? "accepted" : "denied";
+    status.textContent = describeAction(user.action);
+    cell(status);
+
+    if (accepted) {
+        const select = document.createElement("select");
+        ROLES.forEach((role) => select.append(new Option(role, role, false, role === user.action.Accept)));
+        select.addEventListener("change", () => setAction(user.username, { Accept: select.value }));
+        cell(select);
+    } else {
+        cell("");
+    }
+
+    const buttons = document.createElement("span");
+    buttons.append(
+        button(accepted ? "Lock" : "Unlock", () => accepted ? lockUser(user.username) : setAction(user.username, { Accept: user.previous_role || "Limited" })),
+        button("Reset password", () => resetPassword(user.username)),
+        button("Send reset link", () => sendResetLink(user.username)),
+        button("Delete", () => deleteUser(user.username))
+    );
+    cell(buttons);
+    return row;
+}
+
+function button(text, onClick) {
+    const b = document.createElement("button");
+    b.textContent = text;
+    b.addEventListener("click", onClick);
+    return b;
+}
+
+async function report(result, fallback) {
+    showMessage(result.ok ? "" : errorText(result, fallback));
+    await loadUsers();
+}
+
+async function setAction(username, action) {
+    report(await api("PUT", "/api/users/" + encodeURIComponent(username) + "/action", action), "Unable to update " + username);
+}
+
+function lockUser(username) {
+    const reason = prompt("Why is " + username + " being locked?");
+    if (reason) {
+        setAction(username, { Denied: { AccountLocked: { reason } } });
+    }
+}
+
+async function resetPassword(username) {
+    const password = prompt("New password for " + username);
+    if (password) {
+        report(await api("PUT", "/api/users/" + encodeURIComponent(username) + "/password", { password }), "Unable to reset the password");
+    }
+}
+
+async function sendResetLink(username) {
+    const result = await api("POST", "/api/users/" + encodeURIComponent(username) + "/reset-link");
+    showMessage(result.ok ? "Reset link sent to " + username : errorText(result, "Unable to send a reset link"));
+}
+
+async function deleteUser(username) {
+    if (confirm("Delete " + username + "? This can't be undone.")) {
+        report(await api("DELETE", "/api/users/" + encodeURIComponent(username)), "Unable to delete " + username);
+    }
+}
+
+document.getElementById("addUserForm").addEventListener("submit", async (event) => {
+    event.preventDefault();
+    report(await api("POST", "/api/users", {
+        username: document.getElementById("newUsername").value,
+        password: document.getElementById("newPassword").value,
+        role: document.getElementById("newRole").value
+    }), "Unable to add the user");
+    event.target.reset();
+});
+
+document.getElementById("doLogout").addEventListener("click", (event) => {
+    event.preventDefault();
+    logout();
+});
+
+loadUsers();
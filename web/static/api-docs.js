@@ -0,0 +1,80 @@
+// Renders /openapi.json as plain html: an entry per operation, then the schemas.
+
+function element(tag, text, className) {
+    const e = document.createElement(tag);
+    if (text !== undefined) {
+        e.textContent = text;
+    }
+    if (className) {
+        e.className = className;
+    }
+    return e;
+}
+
+function schemaName(schema) {
+    if (!schema) {
+        return "";
+    }
+    if (schema.$ref) {
+        return schema.$ref.split("/").pop();
+    }
+    if (schema.type === "array") {
+        return "[" + schemaName(schema.items) + "]";
+    }
+    return schema.type || "";
+}
+
+function renderOperation(path, method, operation) {
+    const section = element("section");
+    section.append(element("h3", method.toUpperCase() + " " + path));
+    if (operation.tags) {
+        section.append(element("p", "Tags: " + operation.tags.join(", ")));
+    }
+
+    if (operation.parameters) {
+        const list = element("ul");
+        operation.parameters.forEach((p) => {
+            list.append(element("li", p.in + " " + p.name + (p.description ? ": " + p.description : "")));
+        });
+        section.append(element("h4", "Parameters"), list);
+    }
+
+    if (operation.requestBody) {
+        const body = operation.requestBody.content["application/json"];
+        section.append(element("h4", "Request body"), element("p", schemaName(body && body.schema)));
+    }
+
+    const table = element("table");
+    const header = element("tr");
+    ["Status", "Description", "Body"].forEach((h) => header.append(element("th", h)));
+    table.append(header);
+    Object.entries(operation.responses).forEach(([status, response]) => {
+        const row = element("tr");
+        const content = response.content && response.content["application/json"];
+        row.append(
+            element("td", status),
+            element("td", response.description),
+            element("td", schemaName(content && content.schema))
+        );
+        table.append(row);
+    });
+    section.append(element("h4", "Responses"), table);
+    return section;
+}
+
+(async () => {
+    const spec = await (await fetch("/openapi.json")).json();
+    document.getElementById("title").textContent = spec.info.title + " " + spec.info.version;
+
+    const operations = document.getElementById("operations");
+    Object.entries(spec.paths).forEach(([path, methods]) => {
+        Object.entries(methods).forEach(([method, operation]) => {
+            operations.append(renderOperation(path, method, operation));
+        });
+    });
+
+    const schemas = document.getElementById("schemas");
+    Object.entries(spec.components.schemas).forEach(([name, schema]) => {
+        schemas.append(element("h3", name), element("pre", JSON.stringify(schema, null, 2)));
+    });
+})();
@@ -0,0 +1,49 @@
+// Helpers shared by every page. Everything is served by the web binary itself:
+// no CDN, so the pages work on networks without internet access.
+
+const csrfToken = document.querySelector('meta[name="csrf-token"]').content;
+
+// Call the JSON API, echoing the CSRF token. Resolves to
+// `{ ok, status, data }`, where `data` is the parsed body (if any).
+async function api(method, url, body) {
+    const options = { method, headers: { "X-CSRF-Token": csrfToken } };
+    if (body !== undefined) {
+        options.headers["Content-Type"] = "application/json";
+        options.body = JSON.stringify(body);
+    }
+    const response = await fetch(url, options);
+    let data = null;
+    try {
+        data = await response.json();
+    } catch (e) {
+        // No body, e.g. 204 No Content.
+    }
+    return { ok: response.ok, status: response.status, data };
+}
+
+function showMessage(text) {
+    document.getElementById("message").textContent = text;
+}
+
+// The error message from an API error body, or the fallback.
+function errorText(result, fallback) {
+    return (result.data && result.data.error) || fallback;
+}
+
+// A `LoginAction` as sent by the API, e.g. `{"Accept": "Admin"}` or
+// `{"Denied": {"AccountLocked": {"reason": "..."}}}`, in words.
+function describeAction(action) {
+    if (action.Accept) {
+        return action.Accept;
+    }
+    const reason = action.Denied;
+    if (reason.AccountLocked) {
+        return "Locked: " + reason.AccountLocked.reason;
+    }
+    return "Denied: " + reason;
+}
+
+async function logout() {
+    await api("POST", "/api/logout");
+    window.location.href = "/";
+}
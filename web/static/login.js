@@ -0,0 +1,27 @@
+document.getElementById("loginForm").addEventListener("submit", async (event) => {
+    event.preventDefault();
+    const result = await api("POST", "/api/login", {
+        username: document.getElementById("username").value,
+        password: document.getElementById("password").value
+    });
+    switch (result.status) {
+        case 200:
+            window.location.href = result.data.role === "Admin" ? "/admin" : "/protected";
+            break;
+        case 401:
+            showMessage("Invalid login");
+            break;
+        case 403:
+        case 423:
+            // A login denial, or a request refused before it got that far
+            // (a missing CSRF token, say), which only has an error.
+            if (result.data && result.data.denied_reason) {
+                showMessage("Access denied: " + describeAction({ Denied: result.data.denied_reason }));
+            } else {
+                showMessage(errorText(result, "Access denied"));
+            }
+            break;
+        default:
+            showMessage("The login server is unavailable, please try again later");
+    }
+});
@@ -0,0 +1,23 @@
+document.getElementById("passwordForm").addEventListener("submit", async (event) => {
+    event.preventDefault();
+    const newPassword = document.getElementById("newPassword").value;
+    if (newPassword !== document.getElementById("confirmPassword").value) {
+        showMessage("The new passwords don't match");
+        return;
+    }
+    const result = await api("PUT", "/api/me/password", {
+        current_password: document.getElementById("currentPassword").value,
+        new_password: newPassword
+    });
+    if (result.ok) {
+        // Changing the password ends every session, this one included.
+        window.location.href = "/";
+    } else {
+        showMessage(errorText(result, "Unable to change the password"));
+    }
+});
+
+document.getElementById("doLogout").addEventListener("click", (event) => {
+    event.preventDefault();
+    logout();
+});
@@ -0,0 +1,14 @@
+(async () => {
+    const result = await api("GET", "/api/me");
+    if (result.ok) {
+        const me = result.data;
+        document.getElementById("welcome").textContent =
+            "You are logged in as " + me.username + " (" + me.role + ").";
+        document.getElementById("adminLink").hidden = me.role !== "Admin";
+    }
+})();
+
+document.getElementById("doLogout").addEventListener("click", (event) => {
+    event.preventDefault();
+    logout();
+});
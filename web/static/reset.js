@@ -0,0 +1,17 @@
+// The token comes in the link's fragment, which never reaches the server logs.
+const resetToken = new URLSearchParams(window.location.hash.slice(1)).get("token");
+
+document.getElementById("resetForm").addEventListener("submit", async (event) => {
+    event.preventDefault();
+    const password = document.getElementById("newPassword").value;
+    if (password !== document.getElementById("confirmPassword").value) {
+        showMessage("The passwords don't match");
+        return;
+    }
+    const result = await api("POST", "/api/reset-password", { token: resetToken || "", password });
+    if (result.ok) {
+        window.location.href = "/";
+    } else {
+        showMessage(errorText(result, "Unable to reset the password"));
+    }
+});